@@ -0,0 +1,322 @@
+//! API keys authentication
+//!
+//! The clients authenticate with a bearer token: `Authorization: Bearer <key>`.
+//! Each key is scoped to a set of networks and of capabilities. The
+//! capabilities listed as `public` are granted to every client, with or
+//! without a key.
+//!
+//! Requests without a valid key are rejected with `401 Unauthorized`,
+//! requests with a key which does not grant the needed capability with
+//! `403 Forbidden`.
+//!
+//! The endpoint of a request is found by matching its path against the
+//! routes of `handlers::openapi::ENDPOINTS` the way the router does, not
+//! from the segments of the path: an endpoint no capability is listed for
+//! needs a key.
+
+use iron::headers::{Authorization, Bearer};
+use iron::status;
+use iron::{BeforeMiddleware, IronError, IronResult, Request};
+use route_recognizer::Router as Recognizer;
+use std::collections::{HashMap, HashSet};
+use std::{error, fmt};
+
+use super::handlers::common;
+use super::handlers::openapi::ENDPOINTS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Capability {
+    /// query the blocks, the epochs and the tip of the blockchain
+    ReadBlocks,
+    /// query the UTxOs and the chain states
+    ReadUtxos,
+    /// send transactions to the network
+    SubmitTx,
+}
+
+/// what a client needs to use an endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// nothing, the endpoint is open to every client
+    Open,
+    Restricted(Capability),
+    /// a valid key for the network, for the endpoints not listed below
+    AnyKey,
+}
+
+impl Capability {
+    /// the access needed to use the given endpoint (see the names of
+    /// `handlers::openapi::ENDPOINTS`)
+    pub fn required_by(endpoint: &str) -> Access {
+        match endpoint {
+            // the admin API checks the admin key itself
+            "index" | "openapi" | "networks" | "admin_add_network" | "admin_remove_network"
+            | "admin_pause_network" | "admin_resume_network" => Access::Open,
+            "config" | "block" | "epoch" | "pack" | "tip" | "genesis" | "genesis_info"
+            | "genesis_by_hash" | "genesis_balance" | "follow" | "fork" | "time_slot"
+            | "time_at" | "block_at_time" => Access::Restricted(Capability::ReadBlocks),
+            "utxos" | "chain-state" | "chain-state-delta" | "genesis_avvm" => {
+                Access::Restricted(Capability::ReadUtxos)
+            }
+            "txs_signed_send" => Access::Restricted(Capability::SubmitTx),
+            _ => Access::AnyKey,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKey {
+    pub key: String,
+    /// the networks the key gives access to, all of them if not set
+    #[serde(default)]
+    pub networks: Option<HashSet<String>>,
+    pub capabilities: HashSet<Capability>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    /// the capabilities granted without a key
+    #[serde(default)]
+    pub public: HashSet<Capability>,
+    #[serde(default)]
+    pub keys: Vec<ApiKey>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdminConfig {
+    /// the key giving access to the admin API, independent of `auth`
+    pub key: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AuthError {
+    MissingKey,
+    UnknownKey,
+    Forbidden(Capability),
+    ForbiddenNetwork,
+}
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::MissingKey => write!(f, "missing API key"),
+            AuthError::UnknownKey => write!(f, "unknown API key"),
+            AuthError::Forbidden(cap) => write!(f, "API key not allowed to {:?}", cap),
+            AuthError::ForbiddenNetwork => write!(f, "API key not allowed on this network"),
+        }
+    }
+}
+impl error::Error for AuthError {}
+
+pub struct Auth {
+    public: HashSet<Capability>,
+    keys: HashMap<String, ApiKey>,
+    /// the names of the endpoints, by HTTP method
+    routes: HashMap<String, Recognizer<&'static str>>,
+}
+
+impl Auth {
+    pub fn new(config: AuthConfig) -> Self {
+        let mut routes = HashMap::new();
+        for endpoint in ENDPOINTS.iter() {
+            // `/{network}/tip` is routed as `:network/tip`
+            let route = endpoint.path[1..].replace('{', ":").replace('}', "");
+            routes
+                .entry(endpoint.method.to_uppercase())
+                .or_insert_with(Recognizer::new)
+                .add(&route, endpoint.name);
+        }
+        Auth {
+            public: config.public,
+            keys: config
+                .keys
+                .into_iter()
+                .map(|key| (key.key.clone(), key))
+                .collect(),
+            routes: routes,
+        }
+    }
+
+    /// the name of the endpoint the request is routed to and its network,
+    /// `None` if no endpoint matches (the router answers `404`)
+    pub fn get_endpoint(&self, method: &str, path: &[&str]) -> Option<(&'static str, String)> {
+        let recognizer = self.routes.get(method)?;
+        // joined as the router does
+        let matched = recognizer.recognize(&path.join("/")).ok()?;
+        let network = matched.params.find("network").unwrap_or("").to_string();
+        Some((*matched.handler, network))
+    }
+
+    /// check the given key (if any) may use the endpoint of the network
+    pub fn check(
+        &self,
+        network: &str,
+        endpoint: &str,
+        key: Option<&str>,
+    ) -> Result<(), AuthError> {
+        let capability = match Capability::required_by(endpoint) {
+            Access::Open => return Ok(()),
+            Access::Restricted(capability) => Some(capability),
+            Access::AnyKey => None,
+        };
+        if capability.map_or(false, |capability| self.public.contains(&capability)) {
+            return Ok(());
+        }
+        let key = match key {
+            None => return Err(AuthError::MissingKey),
+            Some(key) => match self.keys.get(key) {
+                None => return Err(AuthError::UnknownKey),
+                Some(key) => key,
+            },
+        };
+        let network_allowed = match key.networks {
+            None => true,
+            Some(ref networks) => networks.contains(network),
+        };
+        match capability {
+            _ if !network_allowed => Err(AuthError::ForbiddenNetwork),
+            Some(capability) if !key.capabilities.contains(&capability) => {
+                Err(AuthError::Forbidden(capability))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// the API key of the request, if any
+pub fn get_api_key(req: &Request) -> Option<String> {
+    req.headers
+        .get::<Authorization<Bearer>>()
+        .map(|auth| auth.token.clone())
+}
+
+impl BeforeMiddleware for Auth {
+    fn before(&self, req: &mut Request) -> IronResult<()> {
+        let method = req.method.to_string();
+        let (endpoint, network) = match self.get_endpoint(&method, &req.url.path()) {
+            None => return Ok(()),
+            Some(x) => x,
+        };
+        let key = get_api_key(req);
+        match self.check(&network, endpoint, key.as_ref().map(|k| k.as_str())) {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                warn!("rejected request to {}/{}: {}", network, endpoint, err);
+                let (status, code) = match err {
+                    AuthError::MissingKey => (status::Unauthorized, "missing-api-key"),
+                    AuthError::UnknownKey => (status::Unauthorized, "unknown-api-key"),
+                    AuthError::Forbidden(_) | AuthError::ForbiddenNetwork => {
+                        (status::Forbidden, "forbidden")
+                    }
+                };
+                let mut error: IronError =
+                    common::Error::new(status, code, format!("{}", err)).into();
+                if status == status::Unauthorized {
+                    error
+                        .response
+                        .headers
+                        .set_raw("WWW-Authenticate", vec![b"Bearer".to_vec()]);
+                }
+                Err(error)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn auth() -> Auth {
+        let mut public = HashSet::new();
+        public.insert(Capability::ReadBlocks);
+        let mut capabilities = HashSet::new();
+        capabilities.insert(Capability::SubmitTx);
+        let mut networks = HashSet::new();
+        networks.insert("mainnet".to_string());
+        Auth::new(AuthConfig {
+            public: public,
+            keys: vec![ApiKey {
+                key: "secret".to_string(),
+                networks: Some(networks),
+                capabilities: capabilities,
+            }],
+        })
+    }
+
+    #[test]
+    fn public_capabilities_need_no_key() {
+        assert_eq!(auth().check("mainnet", "block", None), Ok(()));
+        assert_eq!(auth().check("testnet", "tip", Some("unknown")), Ok(()));
+    }
+
+    #[test]
+    fn restricted_capabilities_need_a_key() {
+        assert_eq!(
+            auth().check("mainnet", "txs_signed_send", None),
+            Err(AuthError::MissingKey)
+        );
+        assert_eq!(
+            auth().check("mainnet", "txs_signed_send", Some("unknown")),
+            Err(AuthError::UnknownKey)
+        );
+        assert_eq!(auth().check("mainnet", "txs_signed_send", Some("secret")), Ok(()));
+    }
+
+    #[test]
+    fn keys_are_scoped() {
+        assert_eq!(
+            auth().check("testnet", "txs_signed_send", Some("secret")),
+            Err(AuthError::ForbiddenNetwork)
+        );
+        assert_eq!(
+            auth().check("mainnet", "utxos", Some("secret")),
+            Err(AuthError::Forbidden(Capability::ReadUtxos))
+        );
+    }
+
+    #[test]
+    fn unlisted_endpoints_need_a_key() {
+        assert_eq!(auth().check("mainnet", "unlisted", None), Err(AuthError::MissingKey));
+        assert_eq!(
+            auth().check("mainnet", "unlisted", Some("unknown")),
+            Err(AuthError::UnknownKey)
+        );
+        assert_eq!(
+            auth().check("testnet", "unlisted", Some("secret")),
+            Err(AuthError::ForbiddenNetwork)
+        );
+        assert_eq!(auth().check("mainnet", "unlisted", Some("secret")), Ok(()));
+    }
+
+    #[test]
+    fn endpoints_are_found_like_the_router_does() {
+        let auth = auth();
+        assert_eq!(
+            auth.get_endpoint("POST", &["mainnet", "txs", "signed"]),
+            Some(("txs_signed_send", "mainnet".to_string()))
+        );
+        assert_eq!(
+            auth.get_endpoint("POST", &["", "mainnet", "txs", "signed"]),
+            Some(("txs_signed_send", "mainnet".to_string()))
+        );
+        assert_eq!(
+            auth.get_endpoint("GET", &["mainnet", "chain-state-delta", "1", "2"]),
+            Some(("chain-state-delta", "mainnet".to_string()))
+        );
+        assert_eq!(auth.get_endpoint("GET", &["mainnet", "txs", "signed"]), None);
+    }
+
+    #[test]
+    fn double_slash_does_not_skip_the_key() {
+        let auth = auth();
+        let (endpoint, network) = auth
+            .get_endpoint("POST", &["", "mainnet", "txs", "signed"])
+            .unwrap();
+        assert_eq!(auth.check(&network, endpoint, None), Err(AuthError::MissingKey));
+    }
+}
@@ -0,0 +1,240 @@
+//! walking the blockchain kept in a network's storage
+//!
+//! The storage only knows how to find a block from its hash. The helpers
+//! in this module follow the `previous_header` links between blocks to
+//! answer questions about the shape of the chain: which blocks follow a
+//! given block, where two branches meet...
+
+use cardano::block::{BlockDate, BlockHeader, HeaderHash, RawBlock};
+use cardano_storage::{self, tag, Storage};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::{fmt, result};
+
+#[derive(Debug)]
+pub enum Error {
+    NoTip,
+    BlockNotFound(HeaderHash),
+    /// the branches only meet before their first block
+    NoCommonAncestor,
+    /// more blocks than allowed were walked without finding where the
+    /// branches meet
+    ForkTooDeep(usize),
+    StorageError(cardano_storage::Error),
+    CborError(cbor_event::Error),
+}
+impl From<cardano_storage::Error> for Error {
+    fn from(e: cardano_storage::Error) -> Error {
+        Error::StorageError(e)
+    }
+}
+impl From<cbor_event::Error> for Error {
+    fn from(e: cbor_event::Error) -> Error {
+        Error::CborError(e)
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NoTip => write!(f, "no tip to serve"),
+            Error::BlockNotFound(hh) => write!(f, "block `{}' does not exist", hh),
+            Error::NoCommonAncestor => write!(f, "the blocks have no common ancestor"),
+            Error::ForkTooDeep(max) => {
+                write!(f, "the blocks do not meet within {} blocks", max)
+            }
+            Error::StorageError(err) => write!(f, "storage error: {:?}", err),
+            Error::CborError(err) => write!(f, "invalid block encoding: {:?}", err),
+        }
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// the position of a block within the chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockPoint {
+    pub hash: HeaderHash,
+    pub parent: HeaderHash,
+    pub date: BlockDate,
+}
+impl BlockPoint {
+    pub fn from_header(header: &BlockHeader) -> Self {
+        BlockPoint {
+            hash: header.compute_hash(),
+            parent: header.previous_header(),
+            date: header.blockdate(),
+        }
+    }
+}
+impl Serialize for BlockPoint {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let (epoch, slot) = match self.date {
+            BlockDate::Boundary(epoch) => (epoch, None),
+            BlockDate::Normal(ref slotid) => (slotid.epoch, Some(slotid.slotid)),
+        };
+        let mut state = serializer.serialize_struct("BlockPoint", 4)?;
+        state.serialize_field("hash", &format!("{}", self.hash))?;
+        state.serialize_field("parent", &format!("{}", self.parent))?;
+        state.serialize_field("epoch", &epoch)?;
+        state.serialize_field("slot", &slot)?;
+        state.end()
+    }
+}
+
+/// key to order block dates: the epoch boundary block comes before the
+/// first slot of its epoch
+pub fn date_key(date: &BlockDate) -> (u64, u64) {
+    match date {
+        BlockDate::Boundary(epoch) => (*epoch as u64, 0),
+        BlockDate::Normal(slotid) => (slotid.epoch as u64, slotid.slotid as u64 + 1),
+    }
+}
+
+/// the block currently pointed by the HEAD tag
+pub fn get_tip(storage: &Storage) -> Result<BlockPoint> {
+    match storage.get_block_from_tag(&tag::HEAD) {
+        Err(cardano_storage::Error::NoSuchTag) => Err(Error::NoTip),
+        Err(err) => Err(err.into()),
+        Ok(block) => Ok(BlockPoint::from_header(&block.header())),
+    }
+}
+
+pub fn read_block(storage: &Storage, hash: &HeaderHash) -> Result<RawBlock> {
+    let loc = match storage.block_location(&hash.clone().into()) {
+        Err(_) => return Err(Error::BlockNotFound(hash.clone())),
+        Ok(loc) => loc,
+    };
+    Ok(storage.read_block_at(&loc)?)
+}
+
+pub fn read_point(storage: &Storage, hash: &HeaderHash) -> Result<BlockPoint> {
+    let block = read_block(storage, hash)?.decode()?;
+    Ok(BlockPoint::from_header(&block.header()))
+}
+
+/// collect the blocks of the branch ending at `from` that come after
+/// `cursor`, oldest first. At most the `count` blocks directly following
+/// `cursor` are returned.
+///
+/// `cursor_date` is the date of the cursor's block, `None` if the cursor is
+/// the genesis hash (i.e. before the first block). Returns `None` if the
+/// cursor is not an ancestor of `from`.
+pub fn blocks_after(
+    storage: &Storage,
+    cursor: &HeaderHash,
+    cursor_date: Option<&BlockDate>,
+    from: &HeaderHash,
+    count: usize,
+) -> Result<Option<Vec<BlockPoint>>> {
+    let mut blocks = ::std::collections::VecDeque::with_capacity(count + 1);
+    let mut current = from.clone();
+    while current != *cursor {
+        let point = read_point(storage, &current)?;
+        if let Some(cursor_date) = cursor_date {
+            if date_key(&point.date) <= date_key(cursor_date) {
+                return Ok(None);
+            }
+        }
+        current = point.parent.clone();
+        blocks.push_front(point);
+        if blocks.len() > count {
+            blocks.pop_back();
+        }
+    }
+    Ok(Some(blocks.into_iter().collect()))
+}
+
+/// the most recent block of the branch ending at `from` whose date is not
+/// after `key` (see `date_key`), `None` if the branch reaches `genesis`
+/// before such a block is found
+pub fn block_at(
+    storage: &Storage,
+    genesis: &HeaderHash,
+    from: &HeaderHash,
+    key: (u64, u64),
+) -> Result<Option<BlockPoint>> {
+    let mut current = from.clone();
+    while current != *genesis {
+        let point = read_point(storage, &current)?;
+        if date_key(&point.date) <= key {
+            return Ok(Some(point));
+        }
+        current = point.parent;
+    }
+    Ok(None)
+}
+
+/// the block `depth` blocks before `from` on its branch, `None` if the
+/// branch reaches `genesis` first
+pub fn ancestor(
+    storage: &Storage,
+    genesis: &HeaderHash,
+    from: &HeaderHash,
+    depth: usize,
+) -> Result<Option<HeaderHash>> {
+    let mut current = from.clone();
+    for _ in 0..depth {
+        if current == *genesis {
+            return Ok(None);
+        }
+        current = read_point(storage, &current)?.parent;
+    }
+    if current == *genesis {
+        return Ok(None);
+    }
+    Ok(Some(current))
+}
+
+/// two branches of the chain and where they meet
+#[derive(Debug, Serialize)]
+pub struct Fork {
+    /// the most recent block both branches have in common
+    pub ancestor: BlockPoint,
+    /// the blocks only found on the first branch, most recent first
+    pub left: Vec<BlockPoint>,
+    /// the blocks only found on the second branch, most recent first
+    pub right: Vec<BlockPoint>,
+}
+
+/// find the common ancestor of the blocks `left` and `right`
+///
+/// At most `max_depth` blocks are walked back, if given. The branches of
+/// blocks which only meet at `genesis` have no common ancestor.
+pub fn find_fork(
+    storage: &Storage,
+    genesis: &HeaderHash,
+    left: &HeaderHash,
+    right: &HeaderHash,
+    max_depth: Option<usize>,
+) -> Result<Fork> {
+    let mut l = read_point(storage, left)?;
+    let mut r = read_point(storage, right)?;
+    let mut left_branch = vec![];
+    let mut right_branch = vec![];
+
+    while l.hash != r.hash {
+        if let Some(max_depth) = max_depth {
+            if left_branch.len() + right_branch.len() >= max_depth {
+                return Err(Error::ForkTooDeep(max_depth));
+            }
+        }
+        let (point, branch) = if date_key(&l.date) >= date_key(&r.date) {
+            (&mut l, &mut left_branch)
+        } else {
+            (&mut r, &mut right_branch)
+        };
+        if point.parent == *genesis {
+            return Err(Error::NoCommonAncestor);
+        }
+        let parent = read_point(storage, &point.parent)?;
+        branch.push(::std::mem::replace(point, parent));
+    }
+
+    Ok(Fork {
+        ancestor: l,
+        left: left_branch,
+        right: right_branch,
+    })
+}
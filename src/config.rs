@@ -1,10 +1,25 @@
+use serde_json;
 use serde_yaml;
 
+use super::auth::{AdminConfig, AuthConfig};
+use super::cors::CorsConfig;
+use super::listener::{InvalidListenAddress, ListenAddress};
+use super::rate_limit::RateLimitConfig;
+use super::tls::TlsConfig;
+use super::webhooks::Webhook;
+
+use cardano::block::HeaderHash;
+use cardano::config::{GenesisData, ProtocolMagic};
+use cardano::fee::LinearFee;
 use cardano_storage::config::StorageConfig;
 use cardano_storage::{self, Storage};
 use exe_common::config::net;
+use exe_common::genesisdata;
 use std::collections::HashSet;
-use std::{collections::BTreeMap, num::ParseIntError, sync::Arc, sync::RwLock};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+use std::{collections::BTreeMap, num::ParseIntError, sync::Arc, sync::Mutex, sync::RwLock};
 use std::{
     env::{self, home_dir, VarError},
     io,
@@ -20,6 +35,15 @@ pub enum Error {
     ParseIntError(ParseIntError),
     StorageError(cardano_storage::Error),
     BlockchainConfigError(&'static str),
+    /// no genesis file on disk and none embedded for this hash
+    GenesisNotFound(HeaderHash),
+    /// the genesis file does not hash to the network's `genesis_prev`
+    GenesisMismatch {
+        expected: HeaderHash,
+        found: HeaderHash,
+    },
+    /// the genesis file is not a valid genesis
+    InvalidGenesis(String),
 }
 impl From<VarError> for Error {
     fn from(e: VarError) -> Error {
@@ -49,13 +73,106 @@ impl From<serde_yaml::Error> for Error {
 
 type Result<T> = result::Result<T, Error>;
 
+/// the default port the HTTP server listens to
+pub const DEFAULT_PORT: u16 = 80;
+
+/// resolve a network template: either one of the known templates
+/// (`mainnet', `staging' or `testnet') or the path to a network
+/// configuration file.
+pub fn get_template(template: &str) -> Option<net::Config> {
+    match template {
+        "mainnet" => Some(net::Config::mainnet()),
+        "staging" => Some(net::Config::staging()),
+        "testnet" => Some(net::Config::testnet()),
+        filepath => net::Config::from_file(Path::new(filepath)),
+    }
+}
+
+/// Limits applied to the requests served by the bridge
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    /// number of threads serving the HTTP requests, iron's default if `None`
+    pub http_threads: Option<usize>,
+    /// maximum number of seconds a long-poll request on the tip may wait
+    pub max_long_poll_timeout: u64,
+    /// maximum number of long-poll requests waiting at the same time, each
+    /// of them keeps a thread serving the HTTP requests busy
+    pub max_long_polls: usize,
+    /// maximum number of blocks returned by one request to `follow`
+    pub max_follow_count: usize,
+    /// maximum `depth` of a request to the tip, each block is read from the
+    /// storage
+    pub max_tip_depth: usize,
+    /// maximum number of blocks walked back to find where two branches meet
+    /// (`fork`, `follow`, the webhooks)
+    pub max_fork_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            http_threads: None,
+            max_long_poll_timeout: 120,
+            max_long_polls: 4,
+            max_follow_count: 100,
+            max_tip_depth: 2160,
+            max_fork_depth: 10_000,
+        }
+    }
+}
+
 /// Configuration file for the Wallet CLI
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub root_dir: PathBuf,
     pub port: u16,
+    /// the addresses to listen to, `0.0.0.0:<port>` if empty. See
+    /// `ListenAddress` for the accepted formats.
+    #[serde(default)]
+    pub listen: Vec<String>,
+    /// serve HTTPS instead of HTTP
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// restrict the access to the endpoints, everything is public if `None`
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
+    /// allow cross origin requests, none are allowed if `None`
+    #[serde(default)]
+    pub cors: Option<CorsConfig>,
+    /// enable the admin API, disabled if `None`
+    #[serde(default)]
+    pub admin: Option<AdminConfig>,
+    /// the configuration file, re-read on SIGHUP
+    #[serde(default)]
+    pub config_file: Option<PathBuf>,
+    /// the log level was set on the command line, the one of the
+    /// configuration file is not reloaded
+    #[serde(default)]
+    pub log_level_from_cli: bool,
     pub network_names: HashSet<String>,
+    /// the networks coming from the configuration file, they are added and
+    /// removed as the file changes
+    #[serde(default)]
+    pub file_networks: HashSet<String>,
+    /// the networks are the ones of the configuration file (and not the
+    /// ones given on the command line or the default one)
+    #[serde(default)]
+    pub networks_from_file: bool,
     pub sync: bool,
+    /// the networks not to synchronize even if `sync` is set
+    #[serde(default)]
+    pub sync_disabled: HashSet<String>,
+    #[serde(default)]
+    pub limits: Limits,
+    /// per client rate limits, unlimited if `None`
+    #[serde(default)]
+    pub rate_limits: Option<RateLimitConfig>,
+    /// the endpoints (route names) to serve, all of them if `None`
+    #[serde(default)]
+    pub endpoints: Option<HashSet<String>>,
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
 }
 
 impl Default for Config {
@@ -70,33 +187,85 @@ impl Config {
         Config {
             root_dir: root_dir,
             port: port,
+            listen: Vec::new(),
+            tls: None,
+            auth: None,
+            cors: None,
+            admin: None,
+            config_file: None,
+            log_level_from_cli: false,
             network_names: HashSet::new(),
+            file_networks: HashSet::new(),
+            networks_from_file: false,
             sync: true,
+            sync_disabled: HashSet::new(),
+            limits: Limits::default(),
+            rate_limits: None,
+            endpoints: None,
+            webhooks: Vec::new(),
         }
     }
 
+    /// the addresses the HTTP server listens to
+    pub fn get_listen_addresses(
+        &self,
+    ) -> result::Result<Vec<ListenAddress>, InvalidListenAddress> {
+        if self.listen.is_empty() {
+            let any = ([0, 0, 0, 0], self.port).into();
+            Ok(vec![ListenAddress::Tcp(any)])
+        } else {
+            self.listen.iter().map(|addr| addr.parse()).collect()
+        }
+    }
+
+    pub fn is_endpoint_enabled(&self, endpoint: &str) -> bool {
+        match self.endpoints {
+            None => true,
+            Some(ref endpoints) => endpoints.contains(endpoint),
+        }
+    }
+
+    pub fn is_sync_enabled(&self, name: &str) -> bool {
+        self.sync && !self.sync_disabled.contains(name)
+    }
+
+    /// load the webhooks (a YAML list) from the given file
+    pub fn add_webhooks<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let file = ::std::fs::File::open(path)?;
+        let webhooks: Vec<Webhook> = serde_yaml::from_reader(file)?;
+        self.webhooks.extend(webhooks);
+        Ok(())
+    }
+
     pub fn get_networks_dir(&self) -> PathBuf {
         self.root_dir.clone()
     }
 
     pub fn get_networks(&self) -> Result<Networks> {
-        let mut networks = Networks::new();
+        let networks = Networks::new();
 
         for name in &self.network_names {
-            let netcfg_dir = self.get_networks_dir().join(name);
-
-            let network = Network {
-                path: netcfg_dir,
-                config: self.get_network_config(name)?,
-                storage: Arc::new(RwLock::new(self.get_storage(name)?)),
-            };
-
-            networks.insert(name.to_owned(), network);
+            networks.insert(name.to_owned(), self.get_network(name)?);
         }
 
         Ok(networks)
     }
 
+    /// open the storage of an added network
+    pub fn get_network(&self, name: &str) -> Result<Network> {
+        let path = self.get_networks_dir().join(name);
+        let config = self.get_network_config(name)?;
+        let context = NetworkContext::new(read_genesis(&path, &config.genesis_prev)?)?;
+        Ok(Network {
+            path: path,
+            config: config,
+            context: Arc::new(context),
+            storage: Arc::new(RwLock::new(self.get_storage(name)?)),
+            sync: self.is_sync_enabled(name),
+            state: Arc::new(NetworkState::default()),
+        })
+    }
+
     pub fn get_network_config<P: AsRef<Path>>(&self, name: P) -> Result<net::Config> {
         let path = self.get_networks_dir().join(name).join("config.yml");
         match net::Config::from_file(&path) {
@@ -129,6 +298,55 @@ impl Config {
         Ok(())
     }
 
+    /// stop serving the network, its storage is kept on disk
+    pub fn remove_network(&mut self, name: &str) {
+        self.network_names.remove(name);
+        self.sync_disabled.remove(name);
+        self.file_networks.remove(name);
+    }
+
+    /// add a network described in the configuration file
+    pub fn add_network_entry(&mut self, name: &str, entry: &NetworkEntry) -> Result<()> {
+        let netcfg = match get_template(&entry.template) {
+            None => {
+                error!("unknown or missing template '{}'", entry.template);
+                return Err(Error::BlockchainConfigError(
+                    "unknown or missing network template",
+                ));
+            }
+            Some(netcfg) => netcfg,
+        };
+        self.add_network(name, &netcfg)?;
+        if let Some(ref peers) = entry.peers {
+            self.set_network_peers(name, peers.clone())?;
+        }
+        if let Some(ref genesis) = entry.genesis {
+            self.set_network_genesis(name, &::std::fs::read(genesis)?)?;
+        }
+        if !entry.sync {
+            self.sync_disabled.insert(name.to_string());
+        }
+        self.file_networks.insert(name.to_string());
+        Ok(())
+    }
+
+    /// store the genesis file of an already added network, for the
+    /// networks whose genesis data is not embedded in the bridge
+    pub fn set_network_genesis(&self, name: &str, genesis: &[u8]) -> Result<()> {
+        let netcfg = self.get_network_config(name)?;
+        validate_genesis(genesis, &netcfg.genesis_prev)?;
+        ::std::fs::write(self.get_networks_dir().join(name).join(GENESIS_FILE), genesis)?;
+        Ok(())
+    }
+
+    /// replace the peers of an already added network
+    pub fn set_network_peers(&self, name: &str, peers: net::Peers) -> Result<()> {
+        let mut netcfg = self.get_network_config(name)?;
+        netcfg.peers = peers;
+        netcfg.to_file(self.get_storage_config(name).get_config_file());
+        Ok(())
+    }
+
     pub fn get_storage_config<P: AsRef<Path>>(&self, name: P) -> StorageConfig {
         StorageConfig::new(&self.get_networks_dir().join(name))
     }
@@ -139,11 +357,197 @@ impl Config {
     }
 }
 
+/// a network as described in the bridge configuration file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkEntry {
+    /// the template of the network, see `get_template`
+    pub template: String,
+    /// synchronize the network with its peers
+    #[serde(default = "default_sync")]
+    pub sync: bool,
+    /// override the peers of the template
+    #[serde(default)]
+    pub peers: Option<net::Peers>,
+    /// the genesis file of the network, needed if the bridge does not
+    /// embed it (private chains...)
+    #[serde(default)]
+    pub genesis: Option<PathBuf>,
+}
+
+fn default_sync() -> bool {
+    true
+}
+
+/// The bridge configuration file
+///
+/// every setting is optional: the command line options take precedence
+/// over the file and the defaults apply to what neither of them set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub networks_dir: Option<PathBuf>,
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub listen: Vec<String>,
+    pub tls: Option<TlsConfig>,
+    pub auth: Option<AuthConfig>,
+    pub cors: Option<CorsConfig>,
+    pub admin: Option<AdminConfig>,
+    /// one of `off', `error', `warn', `info', `debug' or `trace'
+    pub log_level: Option<String>,
+    #[serde(default)]
+    pub networks: BTreeMap<String, NetworkEntry>,
+    #[serde(default)]
+    pub limits: Limits,
+    pub rate_limits: Option<RateLimitConfig>,
+    pub endpoints: Option<HashSet<String>>,
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
+}
+
+impl ConfigFile {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = ::std::fs::File::open(path)?;
+        Ok(serde_yaml::from_reader(file)?)
+    }
+
+    /// the log level of the file, `info` if not set
+    pub fn get_log_level(&self) -> result::Result<log::LevelFilter, String> {
+        match self.log_level {
+            None => Ok(log::LevelFilter::Info),
+            Some(ref level) => level
+                .parse::<log::LevelFilter>()
+                .map_err(|_| format!("invalid log level '{}'", level)),
+        }
+    }
+}
+
+/// the name of the genesis file in a network's directory
+pub const GENESIS_FILE: &'static str = "genesis.json";
+
+/// check the genesis file hashes to `genesis_prev`
+fn check_genesis(genesis: &[u8], genesis_prev: &HeaderHash) -> Result<()> {
+    let found = HeaderHash::new(genesis);
+    if &found != genesis_prev {
+        return Err(Error::GenesisMismatch {
+            expected: genesis_prev.clone(),
+            found: found,
+        });
+    }
+    Ok(())
+}
+
+/// parse the genesis file
+///
+/// `genesisdata::parse` panics on a malformed genesis: the JSON is checked
+/// first and the panics of the conversion are caught, a genesis uploaded
+/// with the admin API must not bring the bridge down.
+fn parse_genesis(genesis: &[u8]) -> Result<GenesisData> {
+    serde_json::from_slice::<serde_json::Value>(genesis)
+        .map_err(|err| Error::InvalidGenesis(format!("{}", err)))?;
+    panic::catch_unwind(|| genesisdata::parse::parse(genesis))
+        .map_err(|_| Error::InvalidGenesis("missing or malformed genesis parameters".to_string()))
+}
+
+/// check the genesis file hashes to `genesis_prev` and can be parsed
+pub fn validate_genesis(genesis: &[u8], genesis_prev: &HeaderHash) -> Result<()> {
+    check_genesis(genesis, genesis_prev)?;
+    parse_genesis(genesis).map(|_| ())
+}
+
+/// the genesis file of the network stored in `dir`: its `genesis.json` if
+/// any, the genesis data embedded in the bridge otherwise
+pub fn read_genesis(dir: &Path, genesis_prev: &HeaderHash) -> Result<String> {
+    let path = dir.join(GENESIS_FILE);
+    if !path.exists() {
+        return genesisdata::data::get_genesis_data(genesis_prev)
+            .map(|genesis| genesis.to_string())
+            .map_err(|_| Error::GenesisNotFound(genesis_prev.clone()));
+    }
+    let genesis = ::std::fs::read_to_string(&path)?;
+    check_genesis(genesis.as_bytes(), genesis_prev)?;
+    Ok(genesis)
+}
+
+/// the state of a network which can be changed while the bridge runs,
+/// shared by every copy of the `Network`
+#[derive(Debug, Default)]
+pub struct NetworkState {
+    paused: AtomicBool,
+    removed: AtomicBool,
+    /// a refresher is running for the network
+    refreshing: AtomicBool,
+}
+
+impl NetworkState {
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst)
+    }
+    pub fn is_removed(&self) -> bool {
+        self.removed.load(Ordering::SeqCst)
+    }
+    pub fn is_refreshing(&self) -> bool {
+        self.refreshing.load(Ordering::SeqCst)
+    }
+    pub fn set_refreshing(&self, refreshing: bool) {
+        self.refreshing.store(refreshing, Ordering::SeqCst)
+    }
+}
+
+/// what the requests and the synchronization need to know about a network,
+/// computed once when the network is loaded
+pub struct NetworkContext {
+    /// the genesis file, as is (see `read_genesis`)
+    pub genesis_json: String,
+    pub genesis_data: GenesisData,
+    pub protocol_magic: ProtocolMagic,
+    pub fee_policy: LinearFee,
+    /// the start of the first slot of the first epoch
+    pub start_time: SystemTime,
+    pub slot_duration: Duration,
+    /// `k`: the number of blocks after which a block cannot be rolled back
+    pub epoch_stability_depth: usize,
+}
+
+impl NetworkContext {
+    pub fn new(genesis_json: String) -> Result<Self> {
+        let genesis_data = parse_genesis(genesis_json.as_bytes())?;
+        Ok(NetworkContext {
+            protocol_magic: genesis_data.protocol_magic,
+            fee_policy: genesis_data.fee_policy.clone(),
+            start_time: genesis_data.start_time,
+            slot_duration: genesis_data.slot_duration,
+            epoch_stability_depth: genesis_data.epoch_stability_depth,
+            genesis_json: genesis_json,
+            genesis_data: genesis_data,
+        })
+    }
+
+    /// the number of slots in an epoch: `10 * k`
+    pub fn epoch_slots(&self) -> u64 {
+        10 * self.epoch_stability_depth as u64
+    }
+}
+
 #[derive(Clone)]
 pub struct Network {
     pub path: PathBuf,
     pub config: net::Config,
+    pub context: Arc<NetworkContext>,
     pub storage: Arc<RwLock<cardano_storage::Storage>>,
+    pub sync: bool,
+    pub state: Arc<NetworkState>,
+}
+
+impl Network {
+    /// whether the network is synchronized with its peers
+    pub fn is_syncing(&self) -> bool {
+        self.sync && !self.state.is_paused() && !self.state.is_removed()
+    }
 }
 
 /*
@@ -164,7 +568,88 @@ impl Network {
 }
 */
 
-pub type Networks = BTreeMap<String, Network>;
+/// the networks served by the bridge
+///
+/// Networks may be added and removed while the bridge runs (see the admin
+/// API). The lookups return a copy of the network, which remains usable
+/// by the requests in progress when the network is removed.
+///
+/// The name of a removed network cannot be used again until its refresher
+/// stopped: two refreshers would write to the same storage.
+#[derive(Default)]
+pub struct Networks {
+    networks: RwLock<BTreeMap<String, Network>>,
+    /// the removed networks whose refresher may still be running
+    stopping: Mutex<Vec<(String, Arc<NetworkState>)>>,
+}
+
+impl Networks {
+    pub fn new() -> Self {
+        Networks::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Network> {
+        self.networks.read().unwrap().get(name).cloned()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.networks.read().unwrap().contains_key(name)
+    }
+
+    /// whether a network of that name was removed but its refresher did
+    /// not stop yet
+    pub fn is_stopping(&self, name: &str) -> bool {
+        let mut stopping = self.stopping.lock().unwrap();
+        stopping.retain(|(_, state)| state.is_refreshing());
+        stopping.iter().any(|(n, _)| n == name)
+    }
+
+    /// add the network, returns `false` if the name is already taken or if
+    /// the refresher of the network previously known by that name is
+    /// still running (see `is_stopping`)
+    pub fn insert(&self, name: String, network: Network) -> bool {
+        let mut networks = self.networks.write().unwrap();
+        if networks.contains_key(&name) || self.is_stopping(&name) {
+            return false;
+        }
+        networks.insert(name, network);
+        true
+    }
+
+    /// replace the configuration of the network, e.g. after its
+    /// `config.yml` changed
+    pub fn set_config(&self, name: &str, config: net::Config) {
+        if let Some(network) = self.networks.write().unwrap().get_mut(name) {
+            network.config = config;
+        }
+    }
+
+    /// remove the network, its refresher stops at the end of its current
+    /// round
+    pub fn remove(&self, name: &str) -> Option<Network> {
+        let network = self.networks.write().unwrap().remove(name)?;
+        network.state.removed.store(true, Ordering::SeqCst);
+        self.stopping
+            .lock()
+            .unwrap()
+            .push((name.to_string(), network.state.clone()));
+        Some(network)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.networks.read().unwrap().keys().cloned().collect()
+    }
+
+    /// a snapshot of the networks, ordered by name
+    pub fn to_vec(&self) -> Vec<(String, Network)> {
+        self.networks
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, network)| (name.clone(), network.clone()))
+            .collect()
+    }
+}
 
 /// the environment variable to define where the Hermes files are stores
 ///
@@ -0,0 +1,279 @@
+//! Cross-Origin Resource Sharing
+//!
+//! Allow browser based applications (wallets running in a web page or a
+//! browser extension) to call the bridge directly. The policies are set per
+//! kind of endpoint (see `handlers::common::get_endpoint`), with a default
+//! policy for the kinds not listed.
+//!
+//! The preflight requests (`OPTIONS` with an `Access-Control-Request-Method`
+//! header) are answered before any other processing of the request: they
+//! carry no API key and must not be rejected by the authentication.
+
+use iron::method::Method;
+use iron::status;
+use iron::{AroundMiddleware, Handler, IronResult, Request, Response};
+use std::collections::BTreeMap;
+
+use super::handlers::common;
+use super::snapshot;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CorsPolicy {
+    /// the allowed origins, `*` allows any origin
+    pub origins: Vec<String>,
+    /// the allowed methods
+    #[serde(default = "default_methods")]
+    pub methods: Vec<String>,
+    /// the allowed request headers
+    #[serde(default = "default_headers")]
+    pub headers: Vec<String>,
+    /// number of seconds the browser may cache the preflight response
+    #[serde(default)]
+    pub max_age: Option<u32>,
+}
+
+fn default_methods() -> Vec<String> {
+    vec!["GET".to_string(), "POST".to_string()]
+}
+
+fn default_headers() -> Vec<String> {
+    vec!["Content-Type".to_string(), "Authorization".to_string()]
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CorsConfig {
+    /// the policy of the kinds of endpoints not listed in `routes`, cross
+    /// origin requests are not allowed if not set
+    #[serde(default)]
+    pub default: Option<CorsPolicy>,
+    /// the policies per kind of endpoint (`utxos', `txs', `tip'...)
+    #[serde(default)]
+    pub routes: BTreeMap<String, CorsPolicy>,
+}
+
+impl CorsPolicy {
+    /// the value of the `Access-Control-Allow-Origin` header for the
+    /// origin, if it is allowed
+    fn allow_origin(&self, origin: &str) -> Option<String> {
+        if self.origins.iter().any(|o| o == "*") {
+            Some("*".to_string())
+        } else if self.origins.iter().any(|o| o == origin) {
+            Some(origin.to_string())
+        } else {
+            None
+        }
+    }
+
+    fn allow_method(&self, method: &str) -> bool {
+        self.methods.iter().any(|m| m.eq_ignore_ascii_case(method))
+    }
+
+    /// the response to a preflight request from `origin` for `method`
+    fn preflight(&self, origin: &str, method: &str) -> common::Result<Response> {
+        let allow_origin = match self.allow_origin(origin) {
+            Some(allow_origin) if self.allow_method(method) => allow_origin,
+            _ => {
+                return Err(common::Error::new(
+                    status::Forbidden,
+                    "cors-forbidden",
+                    "cross origin request not allowed",
+                ));
+            }
+        };
+        let mut response = Response::with(status::NoContent);
+        set_header(&mut response, "Access-Control-Allow-Origin", allow_origin);
+        set_header(&mut response, "Access-Control-Allow-Methods", self.methods.join(", "));
+        set_header(&mut response, "Access-Control-Allow-Headers", self.headers.join(", "));
+        if let Some(max_age) = self.max_age {
+            set_header(&mut response, "Access-Control-Max-Age", format!("{}", max_age));
+        }
+        set_header(&mut response, "Vary", "Origin".to_string());
+        Ok(response)
+    }
+
+    /// add the headers of a cross origin request from `origin` to the
+    /// response, if the origin is allowed
+    fn add_headers(&self, origin: &str, response: &mut Response) {
+        if let Some(allow_origin) = self.allow_origin(origin) {
+            set_header(response, "Access-Control-Allow-Origin", allow_origin);
+            set_header(
+                response,
+                "Access-Control-Expose-Headers",
+                snapshot::TIP_HEADER.to_string(),
+            );
+            set_header(response, "Vary", "Origin".to_string());
+        }
+    }
+}
+
+fn get_header(req: &Request, name: &str) -> Option<String> {
+    req.headers
+        .get_raw(name)
+        .and_then(|values| values.get(0))
+        .and_then(|value| String::from_utf8(value.clone()).ok())
+}
+
+fn set_header(response: &mut Response, name: &'static str, value: String) {
+    response.headers.set_raw(name, vec![value.into_bytes()]);
+}
+
+pub struct Cors {
+    config: CorsConfig,
+}
+
+impl Cors {
+    pub fn new(config: CorsConfig) -> Self {
+        Cors { config: config }
+    }
+
+    /// the policy of the kind of endpoint, `None` for the requests which
+    /// are not about a network
+    fn get_policy(&self, kind: Option<&str>) -> Option<&CorsPolicy> {
+        kind.and_then(|kind| self.config.routes.get(kind))
+            .or(self.config.default.as_ref())
+    }
+}
+
+struct CorsHandler {
+    cors: Cors,
+    handler: Box<dyn Handler>,
+}
+
+impl Handler for CorsHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let endpoint = common::get_endpoint(req);
+        let kind = endpoint.as_ref().map(|(_, kind)| kind.as_str());
+        let (policy, origin) = match (self.cors.get_policy(kind), get_header(req, "Origin")) {
+            (Some(policy), Some(origin)) => (policy, origin),
+            _ => return self.handler.handle(req),
+        };
+
+        let request_method = get_header(req, "Access-Control-Request-Method");
+        if let (Method::Options, Some(request_method)) = (&req.method, request_method) {
+            return Ok(policy.preflight(&origin, &request_method)?);
+        }
+
+        match self.handler.handle(req) {
+            Ok(mut response) => {
+                policy.add_headers(&origin, &mut response);
+                Ok(response)
+            }
+            Err(mut error) => {
+                policy.add_headers(&origin, &mut error.response);
+                Err(error)
+            }
+        }
+    }
+}
+
+impl AroundMiddleware for Cors {
+    fn around(self, handler: Box<dyn Handler>) -> Box<dyn Handler> {
+        Box::new(CorsHandler {
+            cors: self,
+            handler: handler,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn policy(origins: &[&str]) -> CorsPolicy {
+        CorsPolicy {
+            origins: origins.iter().map(|o| o.to_string()).collect(),
+            methods: default_methods(),
+            headers: default_headers(),
+            max_age: Some(600),
+        }
+    }
+
+    fn header(response: &Response, name: &str) -> Option<String> {
+        response
+            .headers
+            .get_raw(name)
+            .map(|values| String::from_utf8(values[0].clone()).unwrap())
+    }
+
+    #[test]
+    fn allowed_preflight() {
+        let response = policy(&["https://wallet.example.com"])
+            .preflight("https://wallet.example.com", "POST")
+            .unwrap();
+        assert_eq!(response.status, Some(status::NoContent));
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Origin"),
+            Some("https://wallet.example.com".to_string())
+        );
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Methods"),
+            Some("GET, POST".to_string())
+        );
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Headers"),
+            Some("Content-Type, Authorization".to_string())
+        );
+        assert_eq!(header(&response, "Access-Control-Max-Age"), Some("600".to_string()));
+    }
+
+    #[test]
+    fn forbidden_preflight() {
+        let policy = policy(&["https://wallet.example.com"]);
+        for &(origin, method) in [
+            ("https://evil.example.com", "GET"),
+            ("https://wallet.example.com", "DELETE"),
+        ]
+        .iter()
+        {
+            match policy.preflight(origin, method) {
+                Err(err) => {
+                    assert_eq!(err.status, status::Forbidden);
+                    assert_eq!(err.code, "cors-forbidden");
+                }
+                Ok(_) => panic!("preflight from {} for {} allowed", origin, method),
+            }
+        }
+    }
+
+    #[test]
+    fn simple_request_exposes_the_tip() {
+        let mut response = Response::with(status::Ok);
+        policy(&["*"]).add_headers("https://wallet.example.com", &mut response);
+        assert_eq!(header(&response, "Access-Control-Allow-Origin"), Some("*".to_string()));
+        assert_eq!(
+            header(&response, "Access-Control-Expose-Headers"),
+            Some(snapshot::TIP_HEADER.to_string())
+        );
+
+        let mut response = Response::with(status::Ok);
+        policy(&["https://wallet.example.com"])
+            .add_headers("https://evil.example.com", &mut response);
+        assert_eq!(header(&response, "Access-Control-Allow-Origin"), None);
+        assert_eq!(header(&response, "Access-Control-Expose-Headers"), None);
+    }
+
+    #[test]
+    fn policy_per_kind_of_endpoint() {
+        let mut routes = BTreeMap::new();
+        routes.insert("txs".to_string(), policy(&["https://wallet.example.com"]));
+        let cors = Cors::new(CorsConfig {
+            default: Some(policy(&["*"])),
+            routes: routes,
+        });
+        assert_eq!(
+            cors.get_policy(Some("txs")).unwrap().origins,
+            vec!["https://wallet.example.com".to_string()]
+        );
+        assert_eq!(cors.get_policy(Some("tip")).unwrap().origins, vec!["*".to_string()]);
+        assert_eq!(cors.get_policy(None).unwrap().origins, vec!["*".to_string()]);
+
+        let cors = Cors::new(CorsConfig {
+            default: None,
+            routes: BTreeMap::new(),
+        });
+        assert!(cors.get_policy(Some("tip")).is_none());
+    }
+}
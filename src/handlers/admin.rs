@@ -0,0 +1,307 @@
+//! the admin API: manage the networks while the bridge runs
+//!
+//! Every request must present the admin key (`Authorization: Bearer <key>`).
+//! The networks added here are written to the networks directory like the
+//! ones given on the command line, but they are not added to the
+//! configuration file: they are not served anymore after a restart unless
+//! added again.
+
+use std::fs;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+
+use cryptoxide::util::fixed_time_eq;
+use exe_common::config::net;
+
+use iron;
+use iron::headers::ContentType;
+use iron::status;
+use iron::{IronResult, Request, Response};
+
+use router::Router;
+
+use serde_json;
+
+use super::super::auth;
+use super::super::config::{self, Config, Networks};
+use super::super::service;
+use super::super::webhooks::Notifier;
+use super::common;
+use super::networks::NetworkInfo;
+
+#[derive(Clone, Copy)]
+enum Action {
+    Add,
+    Pause,
+    Resume,
+    Remove,
+}
+
+#[derive(Clone)]
+pub struct Handler {
+    networks: Arc<Networks>,
+    config: Arc<Mutex<Config>>,
+    notifier: Arc<Notifier>,
+    key: String,
+    action: Action,
+}
+impl Handler {
+    pub fn new(
+        networks: Arc<Networks>,
+        config: Arc<Mutex<Config>>,
+        notifier: Arc<Notifier>,
+        key: String,
+    ) -> Self {
+        Handler {
+            networks: networks,
+            config: config,
+            notifier: notifier,
+            key: key,
+            action: Action::Add,
+        }
+    }
+    fn with(&self, action: Action) -> Self {
+        Handler {
+            action: action,
+            ..self.clone()
+        }
+    }
+    pub fn route(self, router: &mut Router) -> &mut Router {
+        router
+            .post(
+                "admin/networks/:network",
+                self.with(Action::Add),
+                "admin_add_network",
+            )
+            .post(
+                "admin/networks/:network/pause",
+                self.with(Action::Pause),
+                "admin_pause_network",
+            )
+            .post(
+                "admin/networks/:network/resume",
+                self.with(Action::Resume),
+                "admin_resume_network",
+            )
+            .delete(
+                "admin/networks/:network",
+                self.with(Action::Remove),
+                "admin_remove_network",
+            )
+    }
+}
+
+/// the body of the request adding a network: either `template` (one of
+/// `mainnet`, `staging` or `testnet`) or `config`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewNetwork {
+    template: Option<String>,
+    config: Option<net::Config>,
+    /// the content of the genesis file, if not embedded in the bridge
+    genesis: Option<String>,
+    #[serde(default = "default_sync")]
+    sync: bool,
+}
+
+fn default_sync() -> bool {
+    true
+}
+
+/// the error answered when the network `name` cannot be added
+fn add_error(name: &str, err: config::Error) -> common::Error {
+    error!("unable to add network {}: {:?}", name, err);
+    match err {
+        config::Error::GenesisMismatch { .. } => common::Error::bad_request(
+            "genesis-mismatch",
+            "the genesis does not match the `genesis_prev` of the network",
+        ),
+        config::Error::GenesisNotFound(_) => common::Error::bad_request(
+            "genesis-not-found",
+            "the genesis of the network must be given",
+        ),
+        config::Error::InvalidGenesis(reason) => {
+            common::Error::bad_request("invalid-genesis", format!("invalid genesis: {}", reason))
+        }
+        _ => common::Error::internal(format!("unable to add network `{}'", name)),
+    }
+}
+
+fn reply(status: status::Status, info: NetworkInfo) -> IronResult<Response> {
+    Ok(Response::with((
+        status,
+        serde_json::to_string(&info).unwrap(),
+        ContentType::json(),
+    )))
+}
+
+impl Handler {
+    fn check_key(&self, req: &Request) -> common::Result<()> {
+        match auth::get_api_key(req) {
+            Some(ref key) if fixed_time_eq(key.as_bytes(), self.key.as_bytes()) => Ok(()),
+            Some(_) => Err(common::Error::new(
+                status::Unauthorized,
+                "unknown-api-key",
+                "invalid admin key",
+            )),
+            None => Err(common::Error::new(
+                status::Unauthorized,
+                "missing-api-key",
+                "missing admin key",
+            )),
+        }
+    }
+
+    fn add(&self, req: &mut Request) -> IronResult<Response> {
+        let name = common::get_param(req, "network")?;
+        if !common::validate_network_name(&name.as_str()) || common::is_reserved_network_name(&name)
+        {
+            return Err(common::Error::bad_request(
+                "invalid-network",
+                format!("invalid network name `{}'", name),
+            )
+            .into());
+        }
+
+        let mut body = String::new();
+        let new_network = req
+            .body
+            .read_to_string(&mut body)
+            .ok()
+            .and_then(|_| serde_json::from_str::<NewNetwork>(&body).ok());
+        let net_cfg = match new_network {
+            Some(NewNetwork {
+                template: Some(ref template),
+                config: None,
+                ..
+            }) => match template.as_str() {
+                "mainnet" => net::Config::mainnet(),
+                "staging" => net::Config::staging(),
+                "testnet" => net::Config::testnet(),
+                _ => {
+                    return Err(common::Error::bad_request(
+                        "unknown-template",
+                        format!("unknown template `{}'", template),
+                    )
+                    .into());
+                }
+            },
+            Some(NewNetwork {
+                template: None,
+                config: Some(ref config),
+                ..
+            }) => config.clone(),
+            _ => {
+                return Err(common::Error::bad_request(
+                    "invalid-body",
+                    "expected a JSON object with either `template` or `config`",
+                )
+                .into());
+            }
+        };
+        let (sync, genesis) = new_network
+            .map(|n| (n.sync, n.genesis))
+            .unwrap_or((true, None));
+        if let Some(ref genesis) = genesis {
+            config::validate_genesis(genesis.as_bytes(), &net_cfg.genesis_prev)
+                .map_err(|err| add_error(&name, err))?;
+        }
+
+        let net = {
+            let mut cfg = self.config.lock().unwrap();
+            if self.networks.contains(&name) {
+                return Err(common::Error::new(
+                    status::Conflict,
+                    "network-exists",
+                    format!("network `{}' already exists", name),
+                )
+                .into());
+            }
+            if self.networks.is_stopping(&name) {
+                return Err(common::Error::new(
+                    status::Conflict,
+                    "network-stopping",
+                    format!(
+                        "network `{}' was removed and is still synchronizing, retry later",
+                        name
+                    ),
+                )
+                .into());
+            }
+            let dir = cfg.get_networks_dir().join(&name);
+            let created = !dir.exists();
+            if !sync {
+                cfg.sync_disabled.insert(name.clone());
+            }
+            let added = cfg
+                .add_network(&name, &net_cfg)
+                .and_then(|()| match genesis {
+                    None => Ok(()),
+                    Some(ref genesis) => cfg.set_network_genesis(&name, genesis.as_bytes()),
+                })
+                .and_then(|()| cfg.get_network(&name))
+                .map_err(|err| add_error(&name, err))
+                .and_then(|net| {
+                    if self.networks.insert(name.clone(), net.clone()) {
+                        Ok(net)
+                    } else {
+                        Err(common::Error::new(
+                            status::Conflict,
+                            "network-exists",
+                            format!("network `{}' already exists or is still stopping", name),
+                        ))
+                    }
+                });
+            match added {
+                Ok(net) => net,
+                Err(err) => {
+                    // leave the configuration and the disk as they were
+                    cfg.remove_network(&name);
+                    if created {
+                        if let Err(err) = fs::remove_dir_all(&dir) {
+                            error!("unable to remove the directory {:?}: {}", dir, err);
+                        }
+                    }
+                    return Err(err.into());
+                }
+            }
+        };
+        info!("network {} added by the admin API", name);
+
+        if net.sync {
+            service::spawn_refresher(name.clone(), net.clone(), self.notifier.clone());
+        }
+        reply(status::Created, NetworkInfo::new(&name, &net)?)
+    }
+
+    fn set_paused(&self, req: &mut Request, paused: bool) -> IronResult<Response> {
+        let (name, net) = common::get_network(req, &self.networks)?;
+        net.state.set_paused(paused);
+        info!(
+            "network {} {} by the admin API",
+            name,
+            if paused { "paused" } else { "resumed" }
+        );
+        reply(status::Ok, NetworkInfo::new(&name, &net)?)
+    }
+
+    fn remove(&self, req: &mut Request) -> IronResult<Response> {
+        let (name, _) = common::get_network(req, &self.networks)?;
+        self.config.lock().unwrap().remove_network(&name);
+        self.networks.remove(&name);
+        info!("network {} removed by the admin API", name);
+        Ok(Response::with(status::NoContent))
+    }
+}
+
+impl iron::Handler for Handler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        self.check_key(req)?;
+        match self.action {
+            Action::Add => self.add(req),
+            Action::Pause => self.set_paused(req, true),
+            Action::Resume => self.set_paused(req, false),
+            Action::Remove => self.remove(req),
+        }
+    }
+}
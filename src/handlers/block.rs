@@ -8,7 +8,6 @@ use iron;
 use iron::status;
 use iron::{IronResult, Request, Response};
 
-use router;
 use router::Router;
 
 use super::common;
@@ -27,30 +26,23 @@ impl Handler {
 
 impl iron::Handler for Handler {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
-        let params = req.extensions.get::<router::Router>().unwrap();
+        let (_, net) = common::get_network(req, &self.networks)?;
 
-        let ref network_name = params.find("network").unwrap();
+        let ref blockid = common::get_param(req, "blockid")?;
 
-        if !common::validate_network_name(network_name) {
-            return Ok(Response::with(status::BadRequest));
-        }
-
-        let net = match self.networks.get(network_name.to_owned()) {
-            None => return Ok(Response::with(status::BadRequest)),
-            Some(net) => net,
+        let invalid_blockid = || {
+            error!("invalid blockid: {}", blockid);
+            common::Error::bad_request("invalid-blockid", format!("invalid block id `{}'", blockid))
         };
 
-        let ref blockid = params.find("blockid").unwrap();
-
         if !blockid.chars().all(|c| c.is_ascii_alphanumeric()) {
-            error!("invalid blockid: {}", blockid);
-            return Ok(Response::with(status::BadRequest));
+            return Err(invalid_blockid().into());
         }
         let hh_bytes = match tag::read(&net.storage.read().unwrap(), &blockid) {
-            None => hex::decode(&blockid).unwrap(),
+            None => hex::decode(&blockid).map_err(|_| invalid_blockid())?,
             Some(t) => t,
         };
-        let hh = block::HeaderHash::try_from_slice(&hh_bytes).expect("blockid invalid");
+        let hh = block::HeaderHash::try_from_slice(&hh_bytes).map_err(|_| invalid_blockid())?;
         info!("querying block header: {}", hh);
 
         match &(net.storage)
@@ -60,14 +52,18 @@ impl iron::Handler for Handler {
         {
             Err(_) => {
                 warn!("block `{}' does not exist", hh);
-                Ok(Response::with((status::NotFound, "Not Found")))
+                Err(common::Error::not_found(
+                    "block-not-found",
+                    format!("block `{}' does not exist", hh),
+                )
+                .into())
             }
             Ok(loc) => {
                 debug!("blk location: {:?}", loc);
                 match net.storage.read().unwrap().read_block_at(&loc) {
                     Err(_) => {
                         error!("error while reading block at location: {:?}", loc);
-                        Ok(Response::with(status::InternalServerError))
+                        Err(common::Error::internal("error while reading block").into())
                     }
                     Ok(rblk) => Ok(Response::with((status::Ok, rblk.as_ref()))),
                 }
@@ -1,5 +1,6 @@
-use cardano_storage::chain_state;
-use exe_common::{genesisdata, sync};
+use cardano::block::HeaderHash;
+use cardano_storage::{chain_state, Storage};
+use exe_common::sync;
 
 use std::sync::Arc;
 
@@ -9,7 +10,9 @@ use iron::{IronResult, Request, Response};
 
 use router::Router;
 
+use super::super::chain::{self, BlockPoint};
 use super::super::config::Networks;
+use super::super::snapshot;
 use super::common;
 
 pub struct Handler {
@@ -24,32 +27,82 @@ impl Handler {
     }
 }
 
+/// whether the block is on the stored (packed) chain, up to the end of the
+/// last packed epoch before it
+///
+/// The epochs are packed once they cannot be rolled back anymore, so one
+/// of the two epochs preceding the block is packed; walking back to it
+/// stays bounded by the size of an epoch.
+fn is_on_stored_chain(
+    storage: &Storage,
+    genesis: &HeaderHash,
+    block: &BlockPoint,
+) -> chain::Result<bool> {
+    let epoch = block.date.get_epochid();
+    let packed = (1..=2)
+        .filter(|n| epoch >= *n)
+        .map(|n| epoch - n)
+        .find_map(|epoch| {
+            chain_state::get_last_block_of_epoch(storage, epoch)
+                .ok()
+                .map(|last| (epoch, last))
+        });
+    let (packed_epoch, last) = match packed {
+        None => return Ok(false),
+        Some(packed) => packed,
+    };
+    let key = (packed_epoch as u64, ::std::u64::MAX);
+    let point = chain::block_at(storage, genesis, &block.hash, key)?;
+    Ok(point.map_or(false, |point| point.hash == last))
+}
+
 impl iron::Handler for Handler {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
-        let (net, epochid) = match common::get_network_and_epoch(req, &self.networks) {
-            None => {
-                return Ok(Response::with(status::BadRequest));
-            }
-            Some(x) => x,
-        };
+        let (net, epochid) = common::get_network_and_epoch(req, &self.networks)?;
 
-        let genesis_str = genesisdata::data::get_genesis_data(&net.config.genesis_prev).unwrap();
-        let genesis_data = genesisdata::parse::parse(genesis_str.as_bytes());
+        let ref genesis_data = net.context.genesis_data;
 
         let storage = net.storage.read().unwrap();
 
-        let chain_state =
-            sync::get_chain_state_at_end_of(&storage, epochid, &genesis_data).unwrap();
+        // pinned with `?at=`: the epoch must be over at that block, and the
+        // stored chain the state is read from must lead to it
+        if let Some(snapshot) = snapshot::get_snapshot(req).filter(|s| s.pinned) {
+            let on_chain = epochid < snapshot.tip.date.get_epochid()
+                && is_on_stored_chain(&storage, &net.config.genesis_prev, &snapshot.tip)
+                    .map_err(common::Error::from)?;
+            if !on_chain {
+                return Err(common::Error::not_found(
+                    "chain-state-not-found",
+                    format!(
+                        "no chain state at the end of epoch {} on the branch of block {}",
+                        epochid, snapshot.tip.hash
+                    ),
+                )
+                .into());
+            }
+        }
+
+        let chain_state = sync::get_chain_state_at_end_of(&storage, epochid, genesis_data)
+            .map_err(|err| {
+                warn!("no chain state at the end of epoch {}: {:?}", epochid, err);
+                common::Error::not_found(
+                    "chain-state-not-found",
+                    format!("no chain state at the end of epoch {}", epochid),
+                )
+            })?;
 
         let mut res = vec![];
         chain_state::write_chain_state_delta(
             &storage,
-            &genesis_data,
+            genesis_data,
             &chain_state,
             &net.config.genesis_prev,
             &mut res,
         )
-        .unwrap();
+        .map_err(|err| {
+            error!("error while writing the chain state: {:?}", err);
+            common::Error::internal("error while writing the chain state")
+        })?;
 
         Ok(Response::with((status::Ok, res)))
     }
@@ -1,5 +1,5 @@
 use cardano_storage::chain_state;
-use exe_common::{genesisdata, sync};
+use exe_common::sync;
 
 use std::sync::Arc;
 
@@ -30,35 +30,43 @@ impl Handler {
 
 impl iron::Handler for Handler {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
-        let (net, from) = match common::get_network_and_epoch(req, &self.networks) {
-            None => {
-                return Ok(Response::with(status::BadRequest));
-            }
-            Some(x) => x,
-        };
+        let (net, from) = common::get_network_and_epoch(req, &self.networks)?;
 
-        let to =
-            common::validate_epochid(&req.extensions.get::<Router>().unwrap().find("to").unwrap())
-                .unwrap();
+        let to = common::get_epochid(req, "to")?;
 
-        let genesis_str = genesisdata::data::get_genesis_data(&net.config.genesis_prev).unwrap();
-        let genesis_data = genesisdata::parse::parse(genesis_str.as_bytes());
+        let ref genesis_data = net.context.genesis_data;
 
         let storage = net.storage.read().unwrap();
 
-        let from_block = chain_state::get_last_block_of_epoch(&storage, from).unwrap();
+        let from_block = chain_state::get_last_block_of_epoch(&storage, from).map_err(|err| {
+            warn!("no last block for epoch {}: {:?}", from, err);
+            common::Error::not_found(
+                "epoch-not-found",
+                format!("epoch {} is not available", from),
+            )
+        })?;
 
-        let to_state = sync::get_chain_state_at_end_of(&storage, to, &genesis_data).unwrap();
+        let to_state = sync::get_chain_state_at_end_of(&storage, to, genesis_data)
+            .map_err(|err| {
+                warn!("no chain state at the end of epoch {}: {:?}", to, err);
+                common::Error::not_found(
+                    "chain-state-not-found",
+                    format!("no chain state at the end of epoch {}", to),
+                )
+            })?;
 
         let mut res = vec![];
         chain_state::write_chain_state_delta(
             &storage,
-            &genesis_data,
+            genesis_data,
             &to_state,
             &from_block,
             &mut res,
         )
-        .unwrap();
+        .map_err(|err| {
+            error!("error while writing the chain state delta: {:?}", err);
+            common::Error::internal("error while writing the chain state delta")
+        })?;
 
         Ok(Response::with((status::Ok, res)))
     }
@@ -1,57 +1,259 @@
+use super::super::chain;
 use super::super::config::{Network, Networks};
-use cardano::block::EpochId;
-use iron::Request;
-use router::Router;
+use super::super::versions::ApiVersion;
+use cardano::block::{EpochId, HeaderHash};
+use iron::headers::ContentType;
+use iron::status;
+use iron::{AfterMiddleware, IronError, IronResult, Request, Response};
+use router::{NoRoute, Router};
+use serde_json;
+use std::{error, fmt, result, str::FromStr};
 
+/// The error of a request
+///
+/// It is sent to the client as a JSON object with the HTTP status of the
+/// error:
+///
+/// ```json
+/// { "code": "block-not-found", "message": "block `...' does not exist", "details": null }
+/// ```
+///
+/// `code` is stable and meant to be matched on by the clients, `message`
+/// is meant for humans and `details` gives, for some errors, more context
+/// about what went wrong.
+#[derive(Debug)]
+pub struct Error {
+    pub status: status::Status,
+    pub code: &'static str,
+    pub message: String,
+    pub details: Option<serde_json::Value>,
+}
+
+impl Error {
+    pub fn new<S: Into<String>>(status: status::Status, code: &'static str, message: S) -> Self {
+        Error {
+            status: status,
+            code: code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn bad_request<S: Into<String>>(code: &'static str, message: S) -> Self {
+        Error::new(status::BadRequest, code, message)
+    }
+
+    pub fn not_found<S: Into<String>>(code: &'static str, message: S) -> Self {
+        Error::new(status::NotFound, code, message)
+    }
+
+    pub fn internal<S: Into<String>>(message: S) -> Self {
+        Error::new(status::InternalServerError, "internal-error", message)
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// the JSON body of the error
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct ErrorBody<'a> {
+            code: &'a str,
+            message: &'a str,
+            details: &'a Option<serde_json::Value>,
+        }
+        serde_json::to_string(&ErrorBody {
+            code: self.code,
+            message: &self.message,
+            details: &self.details,
+        })
+        .unwrap()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.code, self.status, self.message)
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<Error> for IronError {
+    fn from(e: Error) -> IronError {
+        let status = e.status;
+        let body = e.to_json();
+        IronError::new(e, (status, body, ContentType::json()))
+    }
+}
+
+/// answer the requests no route matches with a JSON error, as the others,
+/// instead of the plain `404` of the router
+pub struct NoRouteJson;
+
+impl AfterMiddleware for NoRouteJson {
+    fn catch(&self, req: &mut Request, err: IronError) -> IronResult<Response> {
+        if err.error.is::<NoRoute>() {
+            Err(Error::not_found(
+                "no-route",
+                format!("no endpoint at `/{}'", req.url.path().join("/")),
+            )
+            .into())
+        } else {
+            Err(err)
+        }
+    }
+}
+
+impl From<chain::Error> for Error {
+    fn from(e: chain::Error) -> Error {
+        match e {
+            chain::Error::NoTip => Error::not_found("no-tip", "no tip to serve"),
+            chain::Error::BlockNotFound(_) => Error::not_found("block-not-found", format!("{}", e)),
+            chain::Error::NoCommonAncestor => Error::new(
+                status::UnprocessableEntity,
+                "no-common-ancestor",
+                format!("{}", e),
+            ),
+            chain::Error::ForkTooDeep(_) => {
+                Error::new(status::UnprocessableEntity, "fork-too-deep", format!("{}", e))
+            }
+            _ => {
+                error!("{}", e);
+                Error::internal(format!("{}", e))
+            }
+        }
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// network names are made of letters, digits, `-` and `_`
 pub fn validate_network_name(v: &&str) -> bool {
-    v.chars().all(|c| c.is_ascii_alphanumeric())
+    !v.is_empty()
+        && v
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// the names which cannot name a network: they are the first segment of
+/// the other routes (`/v1/...`, `/admin/...`, `/networks`...)
+pub fn is_reserved_network_name(name: &str) -> bool {
+    ApiVersion::ALL.iter().any(|version| version.prefix() == name)
+        || ["admin", "networks", "openapi.json"].contains(&name)
 }
 
 pub fn validate_epochid(v: &&str) -> Option<EpochId> {
     if !v.chars().all(|c| c.is_digit(10)) {
         None
     } else {
-        Some(v.parse::<EpochId>().unwrap())
+        v.parse::<EpochId>().ok()
     }
 }
 
-pub fn get_network<'a>(req: &Request, networks: &'a Networks) -> Option<(String, &'a Network)> {
-    let ref network_name = req
-        .extensions
-        .get::<Router>()
-        .unwrap()
-        .find("network")
-        .unwrap();
+/// the value of the given parameter of the route
+pub fn get_param(req: &Request, name: &str) -> Result<String> {
+    match req.extensions.get::<Router>().and_then(|p| p.find(name)) {
+        None => Err(Error::bad_request(
+            "missing-parameter",
+            format!("missing parameter `{}'", name),
+        )),
+        Some(value) => Ok(value.to_string()),
+    }
+}
 
-    if !validate_network_name(network_name) {
-        return None;
+/// parse the hex encoded hash given in the parameter `name`
+pub fn parse_hash(name: &str, value: &str) -> Result<HeaderHash> {
+    HeaderHash::from_str(value).map_err(|_| {
+        Error::bad_request(
+            "invalid-hash",
+            format!("invalid hash `{}' for parameter `{}'", value, name),
+        )
+    })
+}
+
+pub fn get_network(req: &Request, networks: &Networks) -> Result<(String, Network)> {
+    let ref network_name = get_param(req, "network")?;
+
+    if !validate_network_name(&network_name.as_str()) {
+        return Err(Error::bad_request(
+            "invalid-network",
+            format!("invalid network name `{}'", network_name),
+        ));
     }
 
-    match networks.get(network_name.to_owned()) {
-        None => None,
-        Some(net) => Some((network_name.to_string(), net)),
+    match networks.get(network_name) {
+        None => Err(Error::not_found(
+            "unknown-network",
+            format!("unknown network `{}'", network_name),
+        )),
+        Some(net) => Ok((network_name.to_string(), net)),
     }
 }
 
-pub fn get_network_and_epoch<'a>(
-    req: &Request,
-    networks: &'a Networks,
-) -> Option<(&'a Network, EpochId)> {
+pub fn get_network_and_epoch(req: &Request, networks: &Networks) -> Result<(Network, EpochId)> {
     let (_, net) = get_network(req, networks)?;
-    let ref epochid_str = req
-        .extensions
-        .get::<Router>()
-        .unwrap()
-        .find("epochid")
-        .unwrap();
+    let epochid = get_epochid(req, "epochid")?;
+    Ok((net, epochid))
+}
 
-    let epochid = match validate_epochid(epochid_str) {
+/// the epoch given in the parameter `name`
+pub fn get_epochid(req: &Request, name: &str) -> Result<EpochId> {
+    let ref epochid_str = get_param(req, name)?;
+
+    match validate_epochid(&epochid_str.as_str()) {
         None => {
             error!("invalid epochid: {}", epochid_str);
-            return None;
+            Err(Error::bad_request(
+                "invalid-epoch",
+                format!("invalid epoch `{}'", epochid_str),
+            ))
+        }
+        Some(e) => Ok(e),
+    }
+}
+
+/// lookup the value of the given key in the query string of the request
+///
+/// only the first occurrence of the key is considered. Values are
+/// returned as they appear in the URL: the parameters this bridge
+/// accepts (hashes, numbers, booleans) never need percent-decoding.
+pub fn get_query_param(req: &Request, key: &str) -> Option<String> {
+    let query = req.url.query()?;
+    query.split('&').find_map(|kv| {
+        let mut kv = kv.splitn(2, '=');
+        if kv.next()? == key {
+            Some(kv.next().unwrap_or("").to_string())
+        } else {
+            None
         }
-        Some(e) => e,
-    };
+    })
+}
 
-    Some((net, epochid))
+/// whether the client lists the given content type in its `Accept` header
+pub fn accepts(req: &Request, content_type: &str) -> bool {
+    req.headers
+        .get_raw("Accept")
+        .map(|values| {
+            values
+                .iter()
+                .any(|value| String::from_utf8_lossy(value).contains(content_type))
+        })
+        .unwrap_or(false)
+}
+
+/// the network and the kind of endpoint a request is for
+///
+/// The kind is the path segment following the network name (`block',
+/// `utxos', `txs'...). Returns `None` for the requests which are not
+/// about a network, and for the paths with empty segments (those are
+/// collapsed by `versions::StripVersion` before this is called).
+pub fn get_endpoint(req: &Request) -> Option<(String, String)> {
+    let path = req.url.path();
+    if path.len() < 2 || path.iter().any(|segment| segment.is_empty()) {
+        return None;
+    }
+    Some((path[0].to_string(), path[1].to_string()))
 }
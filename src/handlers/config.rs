@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use exe_common::config::net;
+
+use iron;
+use iron::headers::ContentType;
+use iron::status;
+use iron::{IronResult, Request, Response};
+
+use router::Router;
+
+use serde_json;
+
+use super::super::config::Networks;
+use super::common;
+
+pub struct Handler {
+    networks: Arc<Networks>,
+}
+impl Handler {
+    pub fn new(networks: Arc<Networks>) -> Self {
+        Handler { networks: networks }
+    }
+    pub fn route(self, router: &mut Router) -> &mut Router {
+        router.get(":network/config", self, "config")
+    }
+}
+
+impl iron::Handler for Handler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let (_, net) = common::get_network(req, &self.networks)?;
+
+        // read from the storage rather than `net.config`: the peers may
+        // have been updated since the bridge started
+        let netcfg_file = net.storage.read().unwrap().config.get_config_file();
+        let net_cfg = net::Config::from_file(&netcfg_file).ok_or_else(|| {
+            error!("no network config present in {:?}", netcfg_file);
+            common::Error::internal("network configuration not found")
+        })?;
+
+        Ok(Response::with((
+            status::Ok,
+            serde_json::to_string(&net_cfg).unwrap(),
+            ContentType::json(),
+        )))
+    }
+}
@@ -25,18 +25,15 @@ impl Handler {
 
 impl iron::Handler for Handler {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
-        let (net, epochid) = match common::get_network_and_epoch(req, &self.networks) {
-            None => {
-                return Ok(Response::with(status::BadRequest));
-            }
-            Some(x) => x,
-        };
+        let (net, epochid) = common::get_network_and_epoch(req, &self.networks)?;
 
         let opackref = epoch::epoch_read_pack(&net.storage.read().unwrap().config, epochid);
         match opackref {
-            Err(_) => {
-                return Ok(Response::with(status::NotFound));
-            }
+            Err(_) => Err(common::Error::not_found(
+                "epoch-not-found",
+                format!("epoch {} is not available", epochid),
+            )
+            .into()),
             Ok(packref) => {
                 let path = net
                     .storage
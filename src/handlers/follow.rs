@@ -0,0 +1,173 @@
+use cardano_storage::chain_state;
+
+use std::sync::Arc;
+
+use iron;
+use iron::status;
+use iron::{IronResult, Request, Response};
+
+use router::Router;
+
+use super::super::chain::{self, BlockPoint};
+use super::super::config::Networks;
+use super::common;
+
+/// number of blocks returned when the client does not specify a `count`
+const DEFAULT_FOLLOW_COUNT: usize = 20;
+
+pub struct Handler {
+    networks: Arc<Networks>,
+    /// maximum number of blocks returned in one roll forward
+    max_count: usize,
+    /// maximum number of blocks walked back to find where the cursor's
+    /// branch meets the main chain
+    max_fork_depth: usize,
+}
+impl Handler {
+    pub fn new(networks: Arc<Networks>, max_count: usize, max_fork_depth: usize) -> Self {
+        Handler {
+            networks: networks,
+            max_count: max_count,
+            max_fork_depth: max_fork_depth,
+        }
+    }
+    pub fn route(self, router: &mut Router) -> &mut Router {
+        router.get(":network/follow", self, "follow")
+    }
+}
+
+#[derive(Serialize)]
+struct FollowBlock {
+    #[serde(flatten)]
+    point: BlockPoint,
+    /// the CBOR encoded block, in base64
+    block: String,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+enum Follow {
+    RollForward {
+        tip: BlockPoint,
+        blocks: Vec<FollowBlock>,
+    },
+    RollBackward {
+        tip: BlockPoint,
+        point: BlockPoint,
+    },
+}
+
+impl iron::Handler for Handler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let (_, net) = common::get_network(req, &self.networks)?;
+
+        // without a cursor the client starts following from the very
+        // beginning of the chain
+        let cursor = match common::get_query_param(req, "cursor") {
+            None => net.config.genesis_prev.clone(),
+            Some(cursor) => common::parse_hash("cursor", &cursor)?,
+        };
+        let count = match common::get_query_param(req, "count") {
+            None => ::std::cmp::min(DEFAULT_FOLLOW_COUNT, self.max_count),
+            Some(count) => match count.parse::<usize>() {
+                Ok(count) if count > 0 => ::std::cmp::min(count, self.max_count),
+                _ => {
+                    return Err(common::Error::bad_request(
+                        "invalid-parameter",
+                        format!("invalid count `{}'", count),
+                    )
+                    .into());
+                }
+            },
+        };
+
+        let storage = net.storage.read().unwrap();
+
+        let tip = chain::get_tip(&storage).map_err(common::Error::from)?;
+
+        let cursor_point = if cursor == net.config.genesis_prev {
+            None
+        } else {
+            match chain::read_point(&storage, &cursor) {
+                Err(chain::Error::BlockNotFound(_)) => {
+                    return Err(common::Error::not_found(
+                        "unknown-cursor",
+                        format!("unknown cursor `{}'", cursor),
+                    )
+                    .into());
+                }
+                Err(err) => return Err(common::Error::from(err).into()),
+                Ok(point) => Some(point),
+            }
+        };
+
+        // Walking back from the tip is linear in the distance between the
+        // cursor and the tip. When the cursor lives in an epoch that has
+        // already been packed, start the walk from the end of that epoch
+        // (or the next one if the cursor is the last block of its epoch)
+        // so the cost of a request stays bounded by the size of an epoch.
+        let from = match cursor_point {
+            None => chain_state::get_last_block_of_epoch(&storage, 0).ok(),
+            Some(ref point) => {
+                let epoch = point.date.get_epochid();
+                match chain_state::get_last_block_of_epoch(&storage, epoch) {
+                    Ok(ref last) if *last == point.hash => {
+                        chain_state::get_last_block_of_epoch(&storage, epoch + 1).ok()
+                    }
+                    Ok(last) => Some(last),
+                    Err(_) => None,
+                }
+            }
+        }
+        .unwrap_or(tip.hash.clone());
+
+        let result = chain::blocks_after(
+            &storage,
+            &cursor,
+            cursor_point.as_ref().map(|point| &point.date),
+            &from,
+            count,
+        )
+        .and_then(|blocks| match blocks {
+            Some(blocks) => {
+                let mut follow_blocks = Vec::with_capacity(blocks.len());
+                for point in blocks {
+                    let raw = chain::read_block(&storage, &point.hash)?;
+                    follow_blocks.push(FollowBlock {
+                        point: point,
+                        block: base64::encode(raw.as_ref()),
+                    });
+                }
+                Ok(Follow::RollForward {
+                    tip: tip.clone(),
+                    blocks: follow_blocks,
+                })
+            }
+            None => {
+                // `from` is on the main chain and the cursor is not an
+                // ancestor of it: the client has to roll back to where its
+                // branch meets the main chain
+                let fork = chain::find_fork(
+                    &storage,
+                    &net.config.genesis_prev,
+                    &cursor,
+                    &from,
+                    Some(self.max_fork_depth),
+                )?;
+                Ok(Follow::RollBackward {
+                    tip: tip.clone(),
+                    point: fork.ancestor,
+                })
+            }
+        });
+
+        let follow = result.map_err(common::Error::from)?;
+
+        let serialized_data = serde_json::to_string(&follow).unwrap();
+
+        let mut response = Response::with((status::Ok, serialized_data));
+        response.headers.set(iron::headers::ContentType::json());
+
+        Ok(response)
+    }
+}
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+
+use iron;
+use iron::headers::ContentType;
+use iron::status;
+use iron::{IronResult, Request, Response};
+
+use router::Router;
+
+use serde_json;
+
+use super::super::chain;
+use super::super::config::Networks;
+use super::common;
+
+/// the common ancestor of two blocks and the blocks of their branches
+pub struct Handler {
+    networks: Arc<Networks>,
+    /// the number of blocks walked back after which the request is refused
+    max_depth: usize,
+}
+impl Handler {
+    pub fn new(networks: Arc<Networks>, max_depth: usize) -> Self {
+        Handler {
+            networks: networks,
+            max_depth: max_depth,
+        }
+    }
+    pub fn route(self, router: &mut Router) -> &mut Router {
+        router.get(":network/fork/:left/:right", self, "fork")
+    }
+}
+
+impl iron::Handler for Handler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let (_, net) = common::get_network(req, &self.networks)?;
+        let left = common::parse_hash("left", &common::get_param(req, "left")?)?;
+        let right = common::parse_hash("right", &common::get_param(req, "right")?)?;
+
+        let storage = net.storage.read().unwrap();
+        let fork = chain::find_fork(
+            &storage,
+            &net.config.genesis_prev,
+            &left,
+            &right,
+            Some(self.max_depth),
+        )
+        .map_err(common::Error::from)?;
+
+        Ok(Response::with((
+            status::Ok,
+            serde_json::to_string(&fork).unwrap(),
+            ContentType::json(),
+        )))
+    }
+}
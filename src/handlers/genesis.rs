@@ -1,14 +1,23 @@
-use std::{str::FromStr, sync::Arc};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
 
-use cardano::block;
+use cardano::address::{Addr, ExtendedAddr};
+use cardano::coin::{self, Coin};
+use cardano::config::ProtocolMagic;
+use cardano::redeem;
+use cardano::tx::{self, TxoPointer};
+use cardano_storage::chain_state;
 use exe_common::genesisdata;
 use iron;
+use iron::headers::ContentType;
 use iron::status;
 use iron::{IronResult, Request, Response};
-use router;
 use router::Router;
+use serde_json;
 
-use super::super::config::Networks;
+use super::super::config::{Network, Networks};
+use super::super::snapshot;
 use super::common;
 
 pub struct Handler {
@@ -25,51 +34,302 @@ impl Handler {
 
 impl iron::Handler for Handler {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
-        let params = req.extensions.get::<router::Router>().unwrap();
+        let (_, net) = common::get_network(req, &self.networks)?;
 
-        let ref network_name = params.find("network").unwrap();
+        let ref hash = common::get_param(req, "hash")?;
 
-        if !common::validate_network_name(network_name) {
-            return Ok(Response::with((status::BadRequest, "Invalid network")));
+        if !hash.chars().all(|c| c.is_ascii_alphanumeric()) {
+            error!("invalid genesis hash: {}", hash);
+            return Err(common::Error::bad_request(
+                "invalid-hash",
+                format!("invalid genesis hash `{}'", hash),
+            )
+            .into());
         }
 
-        let _net = match self.networks.get(network_name.to_owned()) {
+        let header_hash = common::parse_hash("hash", hash)?;
+
+        info!("Searching genesis: {}", header_hash);
+        // the network's own genesis may be a file on disk
+        let genesis_data = if header_hash == net.config.genesis_prev {
+            Some(net.context.genesis_json.clone())
+        } else {
+            genesisdata::data::get_genesis_data(&header_hash)
+                .ok()
+                .map(|genesis| genesis.to_string())
+        };
+
+        match genesis_data {
             None => {
-                return Ok(Response::with((
-                    status::BadRequest,
-                    "Failed to parse network",
-                )));
+                warn!("genesis `{}' does not exist", header_hash);
+                Err(common::Error::not_found(
+                    "genesis-not-found",
+                    format!("genesis `{}' does not exist", header_hash),
+                )
+                .into())
             }
-            Some(net) => net,
-        };
+            Some(json_str) => Ok(Response::with((status::Ok, json_str))),
+        }
+    }
+}
 
-        let ref hash = params.find("hash").unwrap();
+/// the genesis file of the network
+pub struct NetworkHandler {
+    networks: Arc<Networks>,
+}
+impl NetworkHandler {
+    pub fn new(networks: Arc<Networks>) -> Self {
+        NetworkHandler { networks: networks }
+    }
+    pub fn route(self, router: &mut Router) -> &mut Router {
+        router.get(":network/genesis", self, "genesis")
+    }
+}
 
-        if !hash.chars().all(|c| c.is_ascii_alphanumeric()) {
-            error!("invalid genesis hash: {}", hash);
-            return Ok(Response::with((status::BadRequest, "Invalid genesis hash")));
-        }
+impl iron::Handler for NetworkHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let (_, net) = common::get_network(req, &self.networks)?;
+        Ok(Response::with((
+            status::Ok,
+            net.context.genesis_json.clone(),
+            ContentType::json(),
+        )))
+    }
+}
 
-        let header_hash = match block::HeaderHash::from_str(hash) {
-            Err(_) => {
-                error!("failed to parse genesis hash: {}", hash);
-                return Ok(Response::with((
-                    status::BadRequest,
-                    "Failed to parse genesis",
-                )));
+/// the highlights of the genesis of the network
+pub struct InfoHandler {
+    networks: Arc<Networks>,
+}
+impl InfoHandler {
+    pub fn new(networks: Arc<Networks>) -> Self {
+        InfoHandler { networks: networks }
+    }
+    pub fn route(self, router: &mut Router) -> &mut Router {
+        router.get(":network/genesis/info", self, "genesis_info")
+    }
+}
+
+#[derive(Serialize)]
+struct Distribution {
+    count: usize,
+    total: Coin,
+}
+
+impl Distribution {
+    fn new<'a, I: Iterator<Item = &'a Coin>>(coins: I) -> common::Result<Self> {
+        let coins: Vec<Coin> = coins.cloned().collect();
+        Ok(Distribution {
+            count: coins.len(),
+            total: coin::sum_coins(coins.into_iter())
+                .map_err(|_| common::Error::internal("invalid genesis distribution"))?,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct GenesisInfo {
+    genesis_prev: String,
+    /// unix time, in seconds
+    start_time: u64,
+    /// in milliseconds
+    slot_duration: u64,
+    epoch_stability_depth: usize,
+    protocol_magic: ProtocolMagic,
+    /// `txFeePolicy` as given in the genesis file
+    fee_policy: serde_json::Value,
+    avvm: Distribution,
+    non_avvm: Distribution,
+    heavy_delegation_count: usize,
+}
+
+impl GenesisInfo {
+    fn new(net: &Network) -> common::Result<Self> {
+        let context = &net.context;
+        // a few settings are not kept by `GenesisData`
+        let raw: serde_json::Value = serde_json::from_str(&context.genesis_json)
+            .map_err(|_| common::Error::internal("invalid genesis file"))?;
+        let start_time = context
+            .start_time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let slot_duration = context.slot_duration.as_secs() * 1000
+            + u64::from(context.slot_duration.subsec_nanos() / 1_000_000);
+        Ok(GenesisInfo {
+            genesis_prev: format!("{}", net.config.genesis_prev),
+            start_time: start_time,
+            slot_duration: slot_duration,
+            epoch_stability_depth: context.epoch_stability_depth,
+            protocol_magic: context.protocol_magic,
+            fee_policy: raw["blockVersionData"]["txFeePolicy"].clone(),
+            avvm: Distribution::new(context.genesis_data.avvm_distr.values())?,
+            non_avvm: Distribution::new(context.genesis_data.non_avvm_balances.values())?,
+            heavy_delegation_count: raw["heavyDelegation"]
+                .as_object()
+                .map(|dlg| dlg.len())
+                .unwrap_or(0),
+        })
+    }
+}
+
+impl iron::Handler for InfoHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let (_, net) = common::get_network(req, &self.networks)?;
+        let info = GenesisInfo::new(&net)?;
+        Ok(Response::with((
+            status::Ok,
+            serde_json::to_string(&info).unwrap(),
+            ContentType::json(),
+        )))
+    }
+}
+
+/// the initial AVVM balance of a redeem public key
+pub struct AvvmHandler {
+    networks: Arc<Networks>,
+}
+impl AvvmHandler {
+    pub fn new(networks: Arc<Networks>) -> Self {
+        AvvmHandler { networks: networks }
+    }
+    pub fn route(self, router: &mut Router) -> &mut Router {
+        router.get(":network/genesis/avvm/:pubkey", self, "genesis_avvm")
+    }
+}
+
+#[derive(Serialize)]
+struct AvvmBalance {
+    pubkey: String,
+    address: String,
+    amount: Coin,
+    /// the balance has been spent by the tip of the chain
+    redeemed: bool,
+}
+
+/// parse a redeem public key, base64 encoded as in the genesis file
+fn parse_redeem_key(value: &str) -> common::Result<redeem::PublicKey> {
+    // the genesis uses the URL safe alphabet, accept the standard one too
+    let normalized: String = value
+        .chars()
+        .map(|c| match c {
+            '+' => '-',
+            '/' => '_',
+            c => c,
+        })
+        .collect();
+    base64::decode_config(&normalized, base64::URL_SAFE)
+        .ok()
+        .and_then(|bytes| redeem::PublicKey::from_slice(&bytes).ok())
+        .ok_or_else(|| {
+            common::Error::bad_request(
+                "invalid-redeem-key",
+                format!("invalid redeem public key `{}'", value),
+            )
+        })
+}
+
+impl iron::Handler for AvvmHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let (_, net) = common::get_network(req, &self.networks)?;
+        let pubkey_str = common::get_param(req, "pubkey")?;
+        let pubkey = parse_redeem_key(&pubkey_str)?;
+
+        let ref genesis_data = net.context.genesis_data;
+        let amount = match genesis_data.avvm_distr.get(&pubkey) {
+            None => {
+                return Err(common::Error::not_found(
+                    "not-in-genesis",
+                    format!("redeem key `{}' is not in the genesis", pubkey_str),
+                )
+                .into());
             }
-            Ok(hh) => hh,
+            Some(amount) => *amount,
         };
+        let (txid, address) = tx::redeem_pubkey_to_txid(&pubkey, net.context.protocol_magic);
 
-        info!("Searching genesis: {}", header_hash);
-        let genesis_data = genesisdata::data::get_genesis_data(&header_hash);
+        // the genesis output is spent once redeemed, as of the tip read when
+        // the request was received or the one given with `?at=`
+        let redeemed = match snapshot::get_snapshot(req) {
+            None => false,
+            Some(snapshot) => {
+                let storage = net.storage.read().unwrap();
+                let chain_state =
+                    chain_state::restore_chain_state(&storage, genesis_data, &snapshot.tip.hash)
+                        .map_err(|err| {
+                            error!("error while restoring the chain state: {:?}", err);
+                            common::Error::internal("error while restoring the chain state")
+                        })?;
+                !chain_state
+                    .utxos
+                    .contains_key(&TxoPointer { id: txid, index: 0 })
+            }
+        };
 
-        match genesis_data {
-            Err(hh) => {
-                warn!("genesis `{}' does not exist", hh);
-                Ok(Response::with((status::NotFound, "Not Found")))
+        let balance = AvvmBalance {
+            pubkey: pubkey_str,
+            address: format!("{}", address),
+            amount: amount,
+            redeemed: redeemed,
+        };
+        Ok(Response::with((
+            status::Ok,
+            serde_json::to_string(&balance).unwrap(),
+            ContentType::json(),
+        )))
+    }
+}
+
+/// the initial non-AVVM balance of an address
+pub struct BalanceHandler {
+    networks: Arc<Networks>,
+}
+impl BalanceHandler {
+    pub fn new(networks: Arc<Networks>) -> Self {
+        BalanceHandler { networks: networks }
+    }
+    pub fn route(self, router: &mut Router) -> &mut Router {
+        router.get(":network/genesis/balance/:address", self, "genesis_balance")
+    }
+}
+
+#[derive(Serialize)]
+struct Balance {
+    address: String,
+    amount: Coin,
+}
+
+impl iron::Handler for BalanceHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let (_, net) = common::get_network(req, &self.networks)?;
+        let address = common::get_param(req, "address")?;
+        let addr = match ExtendedAddr::from_str(&address) {
+            Ok(addr) => Addr::from(addr),
+            Err(_) => {
+                return Err(common::Error::bad_request(
+                    "invalid-address",
+                    format!("invalid address `{}'", address),
+                )
+                .into());
             }
-            Ok(json_str) => Ok(Response::with((status::Ok, json_str))),
+        };
+
+        let balance = net.context.genesis_data.non_avvm_balances.get(&addr);
+        match balance.cloned() {
+            None => Err(common::Error::not_found(
+                "not-in-genesis",
+                format!("address `{}' has no balance in the genesis", address),
+            )
+            .into()),
+            Some(amount) => Ok(Response::with((
+                status::Ok,
+                serde_json::to_string(&Balance {
+                    address: address,
+                    amount: amount,
+                })
+                .unwrap(),
+                ContentType::json(),
+            ))),
         }
     }
 }
@@ -0,0 +1,75 @@
+use std::sync::Arc;
+
+use iron;
+use iron::headers::ContentType;
+use iron::status;
+use iron::{IronResult, Request, Response};
+
+use router::Router;
+
+use serde_json;
+
+use super::super::config::Networks;
+use super::super::versions::ApiVersion;
+use super::openapi::Endpoint;
+
+pub struct Handler {
+    networks: Arc<Networks>,
+    endpoints: Vec<Endpoint>,
+    version: ApiVersion,
+}
+impl Handler {
+    pub fn new(networks: Arc<Networks>, endpoints: Vec<Endpoint>, version: ApiVersion) -> Self {
+        Handler {
+            networks: networks,
+            endpoints: endpoints,
+            version: version,
+        }
+    }
+    pub fn route(self, router: &mut Router) -> &mut Router {
+        router.get("/", self, "index")
+    }
+}
+
+#[derive(Serialize)]
+struct IndexEndpoint {
+    name: &'static str,
+    method: String,
+    path: String,
+    summary: &'static str,
+}
+
+#[derive(Serialize)]
+struct Index {
+    name: &'static str,
+    version: &'static str,
+    api_version: &'static str,
+    networks: Vec<String>,
+    endpoints: Vec<IndexEndpoint>,
+}
+
+impl iron::Handler for Handler {
+    fn handle(&self, _req: &mut Request) -> IronResult<Response> {
+        let index = Index {
+            name: crate_name!(),
+            version: crate_version!(),
+            api_version: self.version.prefix(),
+            networks: self.networks.names(),
+            endpoints: self
+                .endpoints
+                .iter()
+                .map(|endpoint| IndexEndpoint {
+                    name: endpoint.name,
+                    method: endpoint.method.to_uppercase(),
+                    path: format!("/{}{}", self.version.prefix(), endpoint.path),
+                    summary: endpoint.summary,
+                })
+                .collect(),
+        };
+        Ok(Response::with((
+            status::Ok,
+            serde_json::to_string(&index).unwrap(),
+            ContentType::json(),
+        )))
+    }
+}
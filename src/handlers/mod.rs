@@ -1,10 +1,18 @@
+pub mod admin;
 pub mod block;
 pub mod chain_state;
 pub mod chain_state_delta;
 pub mod common;
+pub mod config;
 pub mod epoch;
+pub mod follow;
+pub mod fork;
 pub mod genesis;
+pub mod index;
+pub mod networks;
+pub mod openapi;
 pub mod pack;
+pub mod time;
 pub mod tip;
 pub mod tx;
 pub mod utxos;
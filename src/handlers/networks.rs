@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+use cardano::config::ProtocolMagic;
+
+use iron;
+use iron::headers::ContentType;
+use iron::status;
+use iron::{IronResult, Request, Response};
+
+use router::Router;
+
+use serde_json;
+
+use super::super::chain::{self, BlockPoint};
+use super::super::config::{Network, Networks};
+use super::common;
+
+pub struct Handler {
+    networks: Arc<Networks>,
+}
+impl Handler {
+    pub fn new(networks: Arc<Networks>) -> Self {
+        Handler { networks: networks }
+    }
+    pub fn route(self, router: &mut Router) -> &mut Router {
+        router.get("networks", self, "networks")
+    }
+}
+
+#[derive(Serialize)]
+pub struct NetworkInfo {
+    name: String,
+    protocol_magic: ProtocolMagic,
+    genesis_prev: String,
+    epoch_stability_depth: usize,
+    sync: bool,
+    /// the synchronization is paused by the admin API
+    paused: bool,
+    /// `None` until the first block is synchronized
+    tip: Option<BlockPoint>,
+}
+
+impl NetworkInfo {
+    pub fn new(name: &str, net: &Network) -> common::Result<Self> {
+        let tip = match chain::get_tip(&net.storage.read().unwrap()) {
+            Ok(tip) => Some(tip),
+            Err(chain::Error::NoTip) => None,
+            Err(err) => return Err(err.into()),
+        };
+        Ok(NetworkInfo {
+            name: name.to_string(),
+            protocol_magic: net.config.protocol_magic,
+            genesis_prev: format!("{}", net.config.genesis_prev),
+            epoch_stability_depth: net.config.epoch_stability_depth,
+            sync: net.sync,
+            paused: net.state.is_paused(),
+            tip: tip,
+        })
+    }
+}
+
+impl iron::Handler for Handler {
+    fn handle(&self, _req: &mut Request) -> IronResult<Response> {
+        let mut networks = vec![];
+        for (name, net) in self.networks.to_vec() {
+            networks.push(NetworkInfo::new(&name, &net)?);
+        }
+        Ok(Response::with((
+            status::Ok,
+            serde_json::to_string(&networks).unwrap(),
+            ContentType::json(),
+        )))
+    }
+}
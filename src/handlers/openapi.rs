@@ -0,0 +1,804 @@
+//! the description of the endpoints offered by the bridge
+//!
+//! `ENDPOINTS` lists every route registered by the handlers. It is used to
+//! serve the OpenAPI 3 document (`GET /openapi.json`) and the index of the
+//! bridge (`GET /`). Keep it in sync with the handlers' `route` functions.
+
+use iron;
+use iron::headers::ContentType;
+use iron::status;
+use iron::{IronResult, Request, Response};
+
+use router::Router;
+
+use serde_json::{self, json, Value};
+
+use super::super::snapshot::TIP_HEADER;
+use super::super::versions::ApiVersion;
+
+pub const CBOR: &'static str = "application/cbor";
+pub const JSON: &'static str = "application/json";
+pub const OCTET_STREAM: &'static str = "application/octet-stream";
+pub const TEXT: &'static str = "text/plain";
+
+#[derive(Clone)]
+pub struct Param {
+    pub name: &'static str,
+    /// either `path` or `query`
+    pub location: &'static str,
+    /// the JSON schema type of the parameter: `string`, `integer`...
+    pub schema: &'static str,
+    pub description: &'static str,
+}
+
+#[derive(Clone)]
+pub struct Endpoint {
+    /// the name of the route, as registered in the router and as listed
+    /// in the `endpoints` setting of the configuration
+    pub name: &'static str,
+    pub method: &'static str,
+    /// the path of the route, in the OpenAPI format
+    pub path: &'static str,
+    pub summary: &'static str,
+    pub params: &'static [Param],
+    /// the content type of the successful response
+    pub content_type: &'static str,
+    /// the name of the schema of the successful response, if JSON
+    pub schema: Option<&'static str>,
+}
+
+const NETWORK: Param = Param {
+    name: "network",
+    location: "path",
+    schema: "string",
+    description: "the name of the network (see `GET /`)",
+};
+
+const EPOCHID: Param = Param {
+    name: "epochid",
+    location: "path",
+    schema: "integer",
+    description: "the epoch number",
+};
+
+const AT: Param = Param {
+    name: "at",
+    location: "query",
+    schema: "string",
+    description: "answer for the chain as of this block instead of the tip (see `X-Cardano-Tip`)",
+};
+
+const UNIX_TS: Param = Param {
+    name: "unix_ts",
+    location: "path",
+    schema: "integer",
+    description: "unix time, in seconds",
+};
+
+pub static ENDPOINTS: &'static [Endpoint] = &[
+    Endpoint {
+        name: "index",
+        method: "get",
+        path: "/",
+        summary: "the networks served by the bridge and the endpoints it offers",
+        params: &[],
+        content_type: JSON,
+        schema: Some("Index"),
+    },
+    Endpoint {
+        name: "openapi",
+        method: "get",
+        path: "/openapi.json",
+        summary: "this OpenAPI document",
+        params: &[],
+        content_type: JSON,
+        schema: None,
+    },
+    Endpoint {
+        name: "networks",
+        method: "get",
+        path: "/networks",
+        summary: "the networks served by the bridge, with their settings and tips",
+        params: &[],
+        content_type: JSON,
+        schema: Some("Networks"),
+    },
+    Endpoint {
+        name: "config",
+        method: "get",
+        path: "/{network}/config",
+        summary: "the configuration of the network",
+        params: &[NETWORK],
+        content_type: JSON,
+        schema: Some("NetworkConfig"),
+    },
+    Endpoint {
+        name: "admin_add_network",
+        method: "post",
+        path: "/admin/networks/{network}",
+        summary: "admin: add a network, from a template or a configuration",
+        params: &[NETWORK],
+        content_type: JSON,
+        schema: Some("Network"),
+    },
+    Endpoint {
+        name: "admin_remove_network",
+        method: "delete",
+        path: "/admin/networks/{network}",
+        summary: "admin: stop serving a network, its storage is kept",
+        params: &[NETWORK],
+        content_type: JSON,
+        schema: None,
+    },
+    Endpoint {
+        name: "admin_pause_network",
+        method: "post",
+        path: "/admin/networks/{network}/pause",
+        summary: "admin: pause the synchronization of a network",
+        params: &[NETWORK],
+        content_type: JSON,
+        schema: Some("Network"),
+    },
+    Endpoint {
+        name: "admin_resume_network",
+        method: "post",
+        path: "/admin/networks/{network}/resume",
+        summary: "admin: resume the synchronization of a network",
+        params: &[NETWORK],
+        content_type: JSON,
+        schema: Some("Network"),
+    },
+    Endpoint {
+        name: "block",
+        method: "get",
+        path: "/{network}/block/{blockid}",
+        summary: "a block, CBOR encoded",
+        params: &[
+            NETWORK,
+            Param {
+                name: "blockid",
+                location: "path",
+                schema: "string",
+                description: "the hash of the block (hex) or the name of a tag",
+            },
+        ],
+        content_type: CBOR,
+        schema: None,
+    },
+    Endpoint {
+        name: "epoch",
+        method: "get",
+        path: "/{network}/epoch/{epochid}",
+        summary: "the pack of the blocks of a (stable) epoch",
+        params: &[NETWORK, EPOCHID],
+        content_type: OCTET_STREAM,
+        schema: None,
+    },
+    Endpoint {
+        name: "pack",
+        method: "get",
+        path: "/{network}/pack/{packid}",
+        summary: "a pack of blocks",
+        params: &[
+            NETWORK,
+            Param {
+                name: "packid",
+                location: "path",
+                schema: "string",
+                description: "the hash of the pack (hex) or the name of a tag",
+            },
+        ],
+        content_type: OCTET_STREAM,
+        schema: None,
+    },
+    Endpoint {
+        name: "genesis_by_hash",
+        method: "get",
+        path: "/{network}/genesis/{hash}",
+        summary: "a genesis file, by hash",
+        params: &[
+            NETWORK,
+            Param {
+                name: "hash",
+                location: "path",
+                schema: "string",
+                description: "the hash of the genesis file (hex)",
+            },
+        ],
+        content_type: JSON,
+        schema: None,
+    },
+    Endpoint {
+        name: "genesis",
+        method: "get",
+        path: "/{network}/genesis",
+        summary: "the genesis file of the network",
+        params: &[NETWORK],
+        content_type: JSON,
+        schema: None,
+    },
+    Endpoint {
+        name: "genesis_info",
+        method: "get",
+        path: "/{network}/genesis/info",
+        summary: "the highlights of the genesis of the network",
+        params: &[NETWORK],
+        content_type: JSON,
+        schema: Some("GenesisInfo"),
+    },
+    Endpoint {
+        name: "genesis_avvm",
+        method: "get",
+        path: "/{network}/genesis/avvm/{pubkey}",
+        summary: "the initial AVVM balance of a redeem key, and whether it was redeemed",
+        params: &[
+            NETWORK,
+            Param {
+                name: "pubkey",
+                location: "path",
+                schema: "string",
+                description: "the redeem public key, base64 (URL safe) encoded as in the genesis",
+            },
+            AT,
+        ],
+        content_type: JSON,
+        schema: Some("AvvmBalance"),
+    },
+    Endpoint {
+        name: "genesis_balance",
+        method: "get",
+        path: "/{network}/genesis/balance/{address}",
+        summary: "the initial non-AVVM balance of an address",
+        params: &[
+            NETWORK,
+            Param {
+                name: "address",
+                location: "path",
+                schema: "string",
+                description: "the address, base58 encoded",
+            },
+        ],
+        content_type: JSON,
+        schema: Some("Balance"),
+    },
+    Endpoint {
+        name: "tip",
+        method: "get",
+        path: "/{network}/tip",
+        summary: "the header of the latest block, CBOR encoded",
+        params: &[
+            NETWORK,
+            Param {
+                name: "after",
+                location: "query",
+                schema: "string",
+                description: "long-poll: wait until the tip differs from this hash",
+            },
+            Param {
+                name: "timeout",
+                location: "query",
+                schema: "integer",
+                description: "long-poll: maximum number of seconds to wait",
+            },
+            Param {
+                name: "depth",
+                location: "query",
+                schema: "integer",
+                description: "return the block this many blocks behind the tip",
+            },
+            Param {
+                name: "stable",
+                location: "query",
+                schema: "boolean",
+                description: "return the block `k` blocks behind the tip, that cannot be rolled back",
+            },
+        ],
+        content_type: CBOR,
+        schema: None,
+    },
+    Endpoint {
+        name: "time_slot",
+        method: "get",
+        path: "/{network}/time/slot/{epoch}/{slot}",
+        summary: "the time a slot starts at",
+        params: &[
+            NETWORK,
+            Param {
+                name: "epoch",
+                location: "path",
+                schema: "integer",
+                description: "the epoch of the slot",
+            },
+            Param {
+                name: "slot",
+                location: "path",
+                schema: "integer",
+                description: "the slot within the epoch",
+            },
+        ],
+        content_type: JSON,
+        schema: Some("SlotTime"),
+    },
+    Endpoint {
+        name: "time_at",
+        method: "get",
+        path: "/{network}/time/at/{unix_ts}",
+        summary: "the epoch and slot running at a given time",
+        params: &[NETWORK, UNIX_TS],
+        content_type: JSON,
+        schema: Some("SlotTime"),
+    },
+    Endpoint {
+        name: "block_at_time",
+        method: "get",
+        path: "/{network}/block-at-time/{unix_ts}",
+        summary: "the most recent block at a given time",
+        params: &[NETWORK, UNIX_TS],
+        content_type: JSON,
+        schema: Some("BlockAtTime"),
+    },
+    Endpoint {
+        name: "txs_signed_send",
+        method: "post",
+        path: "/{network}/txs/signed",
+        summary: "send a signed transaction to the network",
+        params: &[NETWORK],
+        content_type: TEXT,
+        schema: None,
+    },
+    Endpoint {
+        name: "chain-state",
+        method: "get",
+        path: "/{network}/chain-state/{epochid}",
+        summary: "the chain state at the end of an epoch",
+        params: &[NETWORK, EPOCHID, AT],
+        content_type: OCTET_STREAM,
+        schema: None,
+    },
+    Endpoint {
+        name: "chain-state-delta",
+        method: "get",
+        path: "/{network}/chain-state-delta/{epochid}/{to}",
+        summary: "the changes of the chain state between the ends of two epochs",
+        params: &[
+            NETWORK,
+            EPOCHID,
+            Param {
+                name: "to",
+                location: "path",
+                schema: "integer",
+                description: "the epoch number to compute the delta to",
+            },
+        ],
+        content_type: OCTET_STREAM,
+        schema: None,
+    },
+    Endpoint {
+        name: "utxos",
+        method: "get",
+        path: "/{network}/utxos/{address}",
+        summary: "the unspent outputs of an address",
+        params: &[
+            NETWORK,
+            Param {
+                name: "address",
+                location: "path",
+                schema: "string",
+                description: "the address, base58 encoded",
+            },
+            AT,
+        ],
+        content_type: JSON,
+        schema: Some("Utxos"),
+    },
+    Endpoint {
+        name: "follow",
+        method: "get",
+        path: "/{network}/follow",
+        summary: "follow the chain from a cursor, with rollback instructions",
+        params: &[
+            NETWORK,
+            Param {
+                name: "cursor",
+                location: "query",
+                schema: "string",
+                description: "the hash of the last block known to the client",
+            },
+            Param {
+                name: "count",
+                location: "query",
+                schema: "integer",
+                description: "the maximum number of blocks to return",
+            },
+        ],
+        content_type: JSON,
+        schema: Some("Follow"),
+    },
+    Endpoint {
+        name: "fork",
+        method: "get",
+        path: "/{network}/fork/{left}/{right}",
+        summary: "the common ancestor of two blocks and the blocks unique to each branch",
+        params: &[
+            NETWORK,
+            Param {
+                name: "left",
+                location: "path",
+                schema: "string",
+                description: "the hash of the block ending the first branch",
+            },
+            Param {
+                name: "right",
+                location: "path",
+                schema: "string",
+                description: "the hash of the block ending the second branch",
+            },
+        ],
+        content_type: JSON,
+        schema: Some("Fork"),
+    },
+];
+
+/// the endpoints of the given version of the API
+pub fn endpoints(version: ApiVersion) -> Vec<Endpoint> {
+    ENDPOINTS
+        .iter()
+        .map(|endpoint| match (version, endpoint.name) {
+            (ApiVersion::V2, "tip") => Endpoint {
+                summary: "the latest block, as JSON unless `Accept: application/cbor`",
+                content_type: JSON,
+                schema: Some("BlockPoint"),
+                ..endpoint.clone()
+            },
+            _ => endpoint.clone(),
+        })
+        .collect()
+}
+
+fn schemas() -> Value {
+    json!({
+        "Error": {
+            "type": "object",
+            "required": ["code", "message"],
+            "properties": {
+                "code": { "type": "string" },
+                "message": { "type": "string" },
+                "details": {}
+            }
+        },
+        "BlockPoint": {
+            "type": "object",
+            "properties": {
+                "hash": { "type": "string" },
+                "parent": { "type": "string" },
+                "epoch": { "type": "integer" },
+                "slot": { "type": "integer", "nullable": true }
+            }
+        },
+        "Index": {
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "version": { "type": "string" },
+                "api_version": { "type": "string" },
+                "networks": { "type": "array", "items": { "type": "string" } },
+                "endpoints": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "name": { "type": "string" },
+                            "method": { "type": "string" },
+                            "path": { "type": "string" },
+                            "summary": { "type": "string" }
+                        }
+                    }
+                }
+            }
+        },
+        "Network": {
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "protocol_magic": { "type": "integer" },
+                "genesis_prev": { "type": "string" },
+                "epoch_stability_depth": { "type": "integer" },
+                "sync": { "type": "boolean" },
+                "paused": { "type": "boolean" },
+                "tip": {
+                    "nullable": true,
+                    "allOf": [ { "$ref": "#/components/schemas/BlockPoint" } ]
+                }
+            }
+        },
+        "Networks": {
+            "type": "array",
+            "items": { "$ref": "#/components/schemas/Network" }
+        },
+        "NetworkConfig": {
+            "type": "object",
+            "properties": {
+                "genesis": { "type": "string" },
+                "genesis_prev": { "type": "string" },
+                "epoch_start": { "type": "integer" },
+                "protocol_magic": { "type": "integer" },
+                "epoch_stability_depth": { "type": "integer" },
+                "peers": { "type": "array", "items": { "type": "object" } }
+            }
+        },
+        "GenesisInfo": {
+            "type": "object",
+            "properties": {
+                "genesis_prev": { "type": "string" },
+                "start_time": { "type": "integer", "description": "unix time, in seconds" },
+                "slot_duration": { "type": "integer", "description": "in milliseconds" },
+                "epoch_stability_depth": { "type": "integer" },
+                "protocol_magic": { "type": "integer" },
+                "fee_policy": { "type": "object" },
+                "avvm": { "$ref": "#/components/schemas/Distribution" },
+                "non_avvm": { "$ref": "#/components/schemas/Distribution" },
+                "heavy_delegation_count": { "type": "integer" }
+            }
+        },
+        "Distribution": {
+            "type": "object",
+            "properties": {
+                "count": { "type": "integer" },
+                "total": { "type": "integer" }
+            }
+        },
+        "AvvmBalance": {
+            "type": "object",
+            "properties": {
+                "pubkey": { "type": "string" },
+                "address": { "type": "string" },
+                "amount": { "type": "integer" },
+                "redeemed": { "type": "boolean" }
+            }
+        },
+        "Balance": {
+            "type": "object",
+            "properties": {
+                "address": { "type": "string" },
+                "amount": { "type": "integer" }
+            }
+        },
+        "SlotTime": {
+            "type": "object",
+            "properties": {
+                "epoch": { "type": "integer" },
+                "slot": { "type": "integer" },
+                "time": { "type": "integer", "description": "the start of the slot, unix time in seconds" }
+            }
+        },
+        "BlockAtTime": {
+            "type": "object",
+            "properties": {
+                "block": { "$ref": "#/components/schemas/BlockPoint" },
+                "time": { "type": "integer", "description": "the start of the block's slot, unix time in seconds" }
+            }
+        },
+        "Utxos": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "txid": { "type": "string" },
+                    "index": { "type": "integer" },
+                    "address": { "type": "string" },
+                    "coin": { "type": "integer" }
+                }
+            }
+        },
+        "Fork": {
+            "type": "object",
+            "properties": {
+                "ancestor": { "$ref": "#/components/schemas/BlockPoint" },
+                "left": { "type": "array", "items": { "$ref": "#/components/schemas/BlockPoint" } },
+                "right": { "type": "array", "items": { "$ref": "#/components/schemas/BlockPoint" } }
+            }
+        },
+        "Follow": {
+            "type": "object",
+            "properties": {
+                "action": { "type": "string", "enum": ["roll-forward", "roll-backward"] },
+                "tip": { "$ref": "#/components/schemas/BlockPoint" },
+                "point": { "$ref": "#/components/schemas/BlockPoint" },
+                "blocks": {
+                    "type": "array",
+                    "items": {
+                        "allOf": [
+                            { "$ref": "#/components/schemas/BlockPoint" },
+                            {
+                                "type": "object",
+                                "properties": { "block": { "type": "string", "format": "byte" } }
+                            }
+                        ]
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// the schema of the JSON body of the requests which have one
+fn request_body(name: &str) -> Option<Value> {
+    match name {
+        "txs_signed_send" => Some(json!({
+            "type": "object",
+            "required": ["signedTx"],
+            "properties": {
+                "signedTx": {
+                    "type": "string",
+                    "format": "byte",
+                    "description": "the CBOR encoded signed transaction, in base64"
+                }
+            }
+        })),
+        "admin_add_network" => Some(json!({
+            "type": "object",
+            "properties": {
+                "template": { "type": "string", "enum": ["mainnet", "staging", "testnet"] },
+                "config": { "$ref": "#/components/schemas/NetworkConfig" },
+                "genesis": {
+                    "type": "string",
+                    "description": "the content of the genesis file, if not embedded in the bridge"
+                },
+                "sync": { "type": "boolean", "default": true }
+            }
+        })),
+        _ => None,
+    }
+}
+
+fn operation(endpoint: &Endpoint) -> Value {
+    let parameters: Vec<Value> = endpoint
+        .params
+        .iter()
+        .map(|param| {
+            json!({
+                "name": param.name,
+                "in": param.location,
+                "required": param.location == "path",
+                "description": param.description,
+                "schema": { "type": param.schema }
+            })
+        })
+        .collect();
+
+    let content = match endpoint.schema {
+        Some(schema) => json!({
+            endpoint.content_type: {
+                "schema": { "$ref": format!("#/components/schemas/{}", schema) }
+            }
+        }),
+        None if endpoint.content_type == JSON => json!({ JSON: {} }),
+        None => json!({
+            endpoint.content_type: { "schema": { "type": "string", "format": "binary" } }
+        }),
+    };
+    let error = json!({
+        "description": "error",
+        "content": {
+            JSON: { "schema": { "$ref": "#/components/schemas/Error" } }
+        }
+    });
+
+    let mut operation = json!({
+        "operationId": endpoint.name,
+        "summary": endpoint.summary,
+        "parameters": parameters,
+        "responses": {
+            "200": { "description": "success", "content": content },
+            "4XX": error.clone(),
+            "5XX": error
+        }
+    });
+    if endpoint.path.starts_with("/{network}") {
+        operation["responses"]["200"]["headers"] = json!({
+            TIP_HEADER: {
+                "description": "the hash of the tip the request was answered for",
+                "schema": { "type": "string" }
+            }
+        });
+    }
+    if let Some(body) = request_body(endpoint.name) {
+        operation["requestBody"] = json!({
+            "required": true,
+            "content": { JSON: { "schema": body } }
+        });
+    }
+    if endpoint.name.starts_with("admin_") {
+        operation["security"] = json!([ { "adminKey": [] } ]);
+    }
+    operation
+}
+
+/// the OpenAPI 3 document describing the given endpoints of a version
+/// of the API
+pub fn document(endpoints: &[Endpoint], version: ApiVersion) -> Value {
+    let mut paths = serde_json::Map::new();
+    for endpoint in endpoints {
+        let path = paths
+            .entry(endpoint.path.to_string())
+            .or_insert_with(|| json!({}));
+        path[endpoint.method] = operation(endpoint);
+    }
+
+    json!({
+        "openapi": "3.0.0",
+        "info": {
+            "title": crate_name!(),
+            "description": crate_description!(),
+            "version": crate_version!()
+        },
+        "servers": [ { "url": format!("/{}", version.prefix()) } ],
+        "paths": paths,
+        "components": {
+            "schemas": schemas(),
+            "securitySchemes": {
+                "adminKey": { "type": "http", "scheme": "bearer" }
+            }
+        }
+    })
+}
+
+pub struct Handler {
+    document: String,
+}
+impl Handler {
+    pub fn new(endpoints: &[Endpoint], version: ApiVersion) -> Self {
+        Handler {
+            document: serde_json::to_string(&document(endpoints, version)).unwrap(),
+        }
+    }
+    pub fn route(self, router: &mut Router) -> &mut Router {
+        router.get("openapi.json", self, "openapi")
+    }
+}
+
+impl iron::Handler for Handler {
+    fn handle(&self, _req: &mut Request) -> IronResult<Response> {
+        Ok(Response::with((
+            status::Ok,
+            self.document.clone(),
+            ContentType::json(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn path_params_are_documented() {
+        for endpoint in ENDPOINTS {
+            let in_path: Vec<_> = endpoint
+                .path
+                .split('/')
+                .filter(|segment| segment.starts_with('{'))
+                .map(|segment| segment.trim_matches(|c| c == '{' || c == '}'))
+                .collect();
+            let documented: Vec<_> = endpoint
+                .params
+                .iter()
+                .filter(|param| param.location == "path")
+                .map(|param| param.name)
+                .collect();
+            assert_eq!(in_path, documented, "{}", endpoint.name);
+        }
+    }
+
+    #[test]
+    fn every_endpoint_is_in_the_document() {
+        for version in ApiVersion::ALL.iter() {
+            let endpoints = endpoints(*version);
+            let document = document(&endpoints, *version);
+            for endpoint in ENDPOINTS {
+                assert!(document["paths"][endpoint.path][endpoint.method].is_object());
+            }
+        }
+    }
+}
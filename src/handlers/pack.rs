@@ -8,7 +8,6 @@ use iron;
 use iron::status;
 use iron::{IronResult, Request, Response};
 
-use router;
 use router::Router;
 
 use super::common;
@@ -27,39 +26,29 @@ impl Handler {
 
 impl iron::Handler for Handler {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
-        let ref network_name = req
-            .extensions
-            .get::<router::Router>()
-            .unwrap()
-            .find("network")
-            .unwrap();
+        let (_, net) = common::get_network(req, &self.networks)?;
 
-        if !common::validate_network_name(network_name) {
-            return Ok(Response::with(status::BadRequest));
-        }
+        let ref packid = common::get_param(req, "packid")?;
 
-        let net = match self.networks.get(network_name.to_owned()) {
-            None => return Ok(Response::with(status::BadRequest)),
-            Some(net) => net,
+        let invalid_packid = || {
+            error!("invalid packid: {}", packid);
+            common::Error::bad_request("invalid-packid", format!("invalid pack id `{}'", packid))
         };
-        let ref packid = req
-            .extensions
-            .get::<router::Router>()
-            .unwrap()
-            .find("packid")
-            .unwrap();
+
         if !packid
             .chars()
             .all(|c| c.is_ascii_alphanumeric() || c == '_')
         {
-            error!("invalid packid: {}", packid);
-            return Ok(Response::with(status::BadRequest));
+            return Err(invalid_packid().into());
         }
         info!("query pack: {}", packid);
         let packhash_vec = match tag::read(&net.storage.read().unwrap(), &packid) {
-            None => hex::decode(&packid).unwrap(),
+            None => hex::decode(&packid).map_err(|_| invalid_packid())?,
             Some(t) => t,
         };
+        if packhash_vec.len() != HASH_SIZE {
+            return Err(invalid_packid().into());
+        }
 
         let mut packhash = [0; HASH_SIZE];
         packhash[..].clone_from_slice(packhash_vec.as_slice());
@@ -0,0 +1,274 @@
+//! converting between slots and wall-clock time
+//!
+//! Slots follow each other from the start time of the genesis, every one
+//! lasting the slot duration of the genesis. An epoch is made of `10 * k`
+//! slots, `k` being the epoch stability depth.
+
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use cardano_storage::chain_state;
+
+use iron;
+use iron::headers::ContentType;
+use iron::status;
+use iron::{IronResult, Request, Response};
+
+use router::Router;
+
+use serde_json;
+
+use super::super::chain::{self, BlockPoint};
+use super::super::config::{NetworkContext, Networks};
+use super::common;
+
+/// the slot arithmetic of a network, times are in milliseconds since the
+/// unix epoch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clock {
+    start: u64,
+    slot_duration: u64,
+    epoch_slots: u64,
+}
+
+fn as_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_nanos() / 1_000_000)
+}
+
+impl Clock {
+    pub fn new(context: &NetworkContext) -> Self {
+        Clock {
+            start: as_millis(
+                context
+                    .start_time
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or(Duration::from_secs(0)),
+            ),
+            slot_duration: as_millis(context.slot_duration),
+            epoch_slots: context.epoch_slots(),
+        }
+    }
+
+    /// the time the slot starts at, `None` if the slot is not in the epoch
+    pub fn slot_start(&self, epoch: u64, slot: u64) -> Option<u64> {
+        if slot >= self.epoch_slots {
+            return None;
+        }
+        epoch
+            .checked_mul(self.epoch_slots)
+            .and_then(|slots| slots.checked_add(slot))
+            .and_then(|slots| slots.checked_mul(self.slot_duration))
+            .and_then(|offset| offset.checked_add(self.start))
+    }
+
+    /// the epoch and slot running at the given time, `None` before the
+    /// start of the network
+    pub fn slot_at(&self, time: u64) -> Option<(u64, u64)> {
+        if time < self.start || self.slot_duration == 0 || self.epoch_slots == 0 {
+            return None;
+        }
+        let slots = (time - self.start) / self.slot_duration;
+        Some((slots / self.epoch_slots, slots % self.epoch_slots))
+    }
+}
+
+#[derive(Serialize)]
+struct SlotTime {
+    epoch: u64,
+    slot: u64,
+    /// the start of the slot, unix time in seconds
+    time: u64,
+}
+
+/// the number given in the parameter `name`
+fn get_number(req: &Request, name: &str) -> common::Result<u64> {
+    let value = common::get_param(req, name)?;
+    u64::from_str(&value).map_err(|_| {
+        common::Error::bad_request(
+            "invalid-parameter",
+            format!("invalid `{}' for parameter `{}', expected a number", value, name),
+        )
+    })
+}
+
+fn reply<T: ::serde::Serialize>(value: &T) -> IronResult<Response> {
+    Ok(Response::with((
+        status::Ok,
+        serde_json::to_string(value).unwrap(),
+        ContentType::json(),
+    )))
+}
+
+/// the slot running at the unix time (in seconds) of the parameter
+/// `unix_ts`
+fn get_slot_at(req: &Request, clock: &Clock) -> common::Result<(u64, u64)> {
+    let time = get_number(req, "unix_ts")?;
+    time.checked_mul(1000)
+        .and_then(|time| clock.slot_at(time))
+        .ok_or_else(|| {
+            common::Error::bad_request(
+                "before-genesis",
+                format!("`{}' is before the start of the network", time),
+            )
+        })
+}
+
+pub struct SlotHandler {
+    networks: Arc<Networks>,
+}
+impl SlotHandler {
+    pub fn new(networks: Arc<Networks>) -> Self {
+        SlotHandler { networks: networks }
+    }
+    pub fn route(self, router: &mut Router) -> &mut Router {
+        router.get(":network/time/slot/:epoch/:slot", self, "time_slot")
+    }
+}
+
+impl iron::Handler for SlotHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let (_, net) = common::get_network(req, &self.networks)?;
+        let clock = Clock::new(&net.context);
+        let epoch = get_number(req, "epoch")?;
+        let slot = get_number(req, "slot")?;
+        let time = clock.slot_start(epoch, slot).ok_or_else(|| {
+            common::Error::bad_request(
+                "invalid-slot",
+                format!(
+                    "invalid slot `{}', epochs have {} slots",
+                    slot,
+                    net.context.epoch_slots()
+                ),
+            )
+        })?;
+        reply(&SlotTime {
+            epoch: epoch,
+            slot: slot,
+            time: time / 1000,
+        })
+    }
+}
+
+pub struct AtHandler {
+    networks: Arc<Networks>,
+}
+impl AtHandler {
+    pub fn new(networks: Arc<Networks>) -> Self {
+        AtHandler { networks: networks }
+    }
+    pub fn route(self, router: &mut Router) -> &mut Router {
+        router.get(":network/time/at/:unix_ts", self, "time_at")
+    }
+}
+
+impl iron::Handler for AtHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let (_, net) = common::get_network(req, &self.networks)?;
+        let clock = Clock::new(&net.context);
+        let (epoch, slot) = get_slot_at(req, &clock)?;
+        reply(&SlotTime {
+            epoch: epoch,
+            slot: slot,
+            time: clock.slot_start(epoch, slot).unwrap() / 1000,
+        })
+    }
+}
+
+/// the most recent block at the given time
+pub struct BlockHandler {
+    networks: Arc<Networks>,
+}
+impl BlockHandler {
+    pub fn new(networks: Arc<Networks>) -> Self {
+        BlockHandler { networks: networks }
+    }
+    pub fn route(self, router: &mut Router) -> &mut Router {
+        router.get(":network/block-at-time/:unix_ts", self, "block_at_time")
+    }
+}
+
+#[derive(Serialize)]
+struct BlockAtTime {
+    block: BlockPoint,
+    /// the start of the block's slot, unix time in seconds
+    time: u64,
+}
+
+impl iron::Handler for BlockHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let (_, net) = common::get_network(req, &self.networks)?;
+        let clock = Clock::new(&net.context);
+        let (epoch, slot) = get_slot_at(req, &clock)?;
+
+        let storage = net.storage.read().unwrap();
+        let tip = chain::get_tip(&storage).map_err(common::Error::from)?;
+
+        // as in `follow`, start the walk from the end of the epoch when it
+        // has been packed so it stays bounded by the size of an epoch
+        let from = if epoch < tip.date.get_epochid() as u64 {
+            chain_state::get_last_block_of_epoch(&storage, epoch as _).ok()
+        } else {
+            None
+        }
+        .unwrap_or(tip.hash.clone());
+
+        let block = chain::block_at(&storage, &net.config.genesis_prev, &from, (epoch, slot + 1))
+            .map_err(common::Error::from)?
+            .ok_or_else(|| {
+                common::Error::not_found("no-block", "no block at the given time")
+            })?;
+        let (block_epoch, block_slot) = chain::date_key(&block.date);
+        let time = clock
+            .slot_start(block_epoch, block_slot.saturating_sub(1))
+            .unwrap();
+        reply(&BlockAtTime {
+            block: block,
+            time: time / 1000,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // mainnet: started 2017-09-23T21:44:51Z, 20 seconds slots, k = 2160
+    const MAINNET: Clock = Clock {
+        start: 1_506_203_091_000,
+        slot_duration: 20_000,
+        epoch_slots: 21_600,
+    };
+
+    #[test]
+    fn first_slot_starts_with_the_network() {
+        assert_eq!(MAINNET.slot_start(0, 0), Some(MAINNET.start));
+        assert_eq!(MAINNET.slot_at(MAINNET.start), Some((0, 0)));
+        assert_eq!(MAINNET.slot_at(MAINNET.start - 1), None);
+    }
+
+    #[test]
+    fn epoch_boundary() {
+        let last = MAINNET.slot_start(0, 21_599).unwrap();
+        let next = MAINNET.slot_start(1, 0).unwrap();
+        assert_eq!(next - last, 20_000);
+        assert_eq!(MAINNET.slot_at(next - 1), Some((0, 21_599)));
+        assert_eq!(MAINNET.slot_at(next), Some((1, 0)));
+        assert_eq!(MAINNET.slot_start(0, 21_600), None);
+    }
+
+    #[test]
+    fn slot_roundtrip() {
+        for &(epoch, slot) in [(0, 1), (1, 21_599), (208, 8_000), (400, 0)].iter() {
+            let time = MAINNET.slot_start(epoch, slot).unwrap();
+            assert_eq!(MAINNET.slot_at(time), Some((epoch, slot)));
+            assert_eq!(MAINNET.slot_at(time + 19_999), Some((epoch, slot)));
+        }
+    }
+
+    #[test]
+    fn known_epoch_start() {
+        // epoch 208 started 2020-07-29T21:44:51Z
+        assert_eq!(MAINNET.slot_start(208, 0), Some(1_596_059_091_000));
+    }
+}
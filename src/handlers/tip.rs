@@ -1,59 +1,259 @@
-use super::super::config::Networks;
+use super::super::chain::{self, BlockPoint};
+use super::super::config::{Network, Networks};
+use super::super::snapshot;
+use cardano::util::hex;
 use cardano_storage::{tag, Error};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use iron;
+use iron::headers::ContentType;
 use iron::status;
 use iron::{IronResult, Request, Response};
 
-use router;
 use router::Router;
 
+use serde_json;
+
 use super::common;
+use super::openapi::CBOR;
+
+/// default number of seconds a long-poll request waits for a new tip
+const DEFAULT_LONG_POLL_TIMEOUT: u64 = 30;
+
+/// how often the HEAD tag is checked while a long-poll request is waiting
+const LONG_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// the number of long-poll requests waiting, shared by the handlers of
+/// every version of the API
+pub struct LongPolls {
+    max: usize,
+    waiting: AtomicUsize,
+}
+impl LongPolls {
+    pub fn new(max: usize) -> Self {
+        LongPolls {
+            max: max,
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// count a new waiting request, `None` if there are too many already
+    fn acquire(&self) -> Option<LongPoll> {
+        let mut waiting = self.waiting.load(Ordering::SeqCst);
+        loop {
+            if waiting >= self.max {
+                return None;
+            }
+            match self.waiting.compare_exchange(
+                waiting,
+                waiting + 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => return Some(LongPoll { polls: self }),
+                Err(current) => waiting = current,
+            }
+        }
+    }
+}
+
+/// a waiting long-poll request, no longer counted once dropped
+struct LongPoll<'a> {
+    polls: &'a LongPolls,
+}
+impl<'a> Drop for LongPoll<'a> {
+    fn drop(&mut self) {
+        self.polls.waiting.fetch_sub(1, Ordering::SeqCst);
+    }
+}
 
 pub struct Handler {
     networks: Arc<Networks>,
+    /// maximum number of seconds a long-poll request may wait for a new tip
+    max_long_poll_timeout: u64,
+    long_polls: Arc<LongPolls>,
+    /// maximum number of blocks behind the HEAD a client may ask for
+    max_depth: usize,
+    /// answer with the JSON description of the tip, unless the client asks
+    /// for CBOR with `Accept: application/cbor`
+    json_by_default: bool,
 }
 impl Handler {
-    pub fn new(networks: Arc<Networks>) -> Self {
-        Handler { networks: networks }
+    pub fn new(
+        networks: Arc<Networks>,
+        max_long_poll_timeout: u64,
+        long_polls: Arc<LongPolls>,
+        max_depth: usize,
+    ) -> Self {
+        Handler {
+            networks: networks,
+            max_long_poll_timeout: max_long_poll_timeout,
+            long_polls: long_polls,
+            max_depth: max_depth,
+            json_by_default: false,
+        }
+    }
+    pub fn json_by_default(mut self) -> Self {
+        self.json_by_default = true;
+        self
     }
     pub fn route(self, router: &mut Router) -> &mut Router {
         router.get(":network/tip", self, "tip")
     }
 }
 
+/// block until the HEAD of the given network is different from `after`
+/// or until `timeout` has elapsed.
+///
+/// Returns `true` if the HEAD has changed.
+fn wait_for_new_head(net: &Network, after: &[u8], timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        // the storage lock is only held for the time of the read so the
+        // refresher can still update the HEAD while we are waiting
+        match tag::read(&net.storage.read().unwrap(), &tag::HEAD) {
+            Some(ref head) if head.as_slice() != after => return true,
+            _ => {}
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return false;
+        }
+        thread::sleep(::std::cmp::min(LONG_POLL_INTERVAL, deadline - now));
+    }
+}
+
+/// how many blocks behind the HEAD the client asks for: `?depth=N` (at most
+/// `max_depth`), or `k` with `?stable=true`
+fn get_depth(
+    req: &Request,
+    epoch_stability_depth: usize,
+    max_depth: usize,
+) -> common::Result<usize> {
+    let depth = common::get_query_param(req, "depth");
+    let stable = common::get_query_param(req, "stable");
+    match (depth, stable.as_ref().map(|s| s.as_str())) {
+        (None, None) | (None, Some("false")) => Ok(0),
+        (None, Some("true")) => Ok(epoch_stability_depth),
+        (Some(depth), None) => match depth.parse::<usize>() {
+            Ok(depth) if depth <= max_depth => Ok(depth),
+            Ok(_) => Err(common::Error::bad_request(
+                "invalid-parameter",
+                format!("depth `{}' is over the maximum of {}", depth, max_depth),
+            )),
+            Err(_) => Err(common::Error::bad_request(
+                "invalid-parameter",
+                format!("invalid depth `{}'", depth),
+            )),
+        },
+        (None, Some(stable)) => Err(common::Error::bad_request(
+            "invalid-parameter",
+            format!("invalid value `{}' for parameter `stable'", stable),
+        )),
+        (Some(_), Some(_)) => Err(common::Error::bad_request(
+            "invalid-parameter",
+            "`depth' and `stable' cannot be given together",
+        )),
+    }
+}
+
 impl iron::Handler for Handler {
     // XXX
     //
     // The current implementation of the TIP handler is to look for the HEAD tag
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
-        let ref network_name = req
-            .extensions
-            .get::<router::Router>()
-            .unwrap()
-            .find("network")
-            .unwrap();
-
-        if !common::validate_network_name(network_name) {
-            return Ok(Response::with(status::BadRequest));
+        let (_, net) = common::get_network(req, &self.networks)?;
+
+        // long-poll: `?after=<hash>&timeout=<secs>` waits until the HEAD moves
+        if let Some(after) = common::get_query_param(req, "after") {
+            let after = hex::decode(&after).map_err(|_| {
+                error!("invalid long-poll hash: {}", after);
+                common::Error::bad_request(
+                    "invalid-hash",
+                    format!("invalid hash `{}' for parameter `after'", after),
+                )
+            })?;
+            let timeout = match common::get_query_param(req, "timeout") {
+                None => ::std::cmp::min(DEFAULT_LONG_POLL_TIMEOUT, self.max_long_poll_timeout),
+                Some(timeout) => match timeout.parse::<u64>() {
+                    Ok(timeout) => ::std::cmp::min(timeout, self.max_long_poll_timeout),
+                    Err(_) => {
+                        return Err(common::Error::bad_request(
+                            "invalid-parameter",
+                            format!("invalid timeout `{}'", timeout),
+                        )
+                        .into());
+                    }
+                },
+            };
+
+            let _long_poll = match self.long_polls.acquire() {
+                None => {
+                    return Err(common::Error::new(
+                        status::ServiceUnavailable,
+                        "too-many-long-polls",
+                        "too many long-poll requests are waiting, retry later",
+                    )
+                    .into());
+                }
+                Some(long_poll) => long_poll,
+            };
+            if !wait_for_new_head(&net, &after, Duration::from_secs(timeout)) {
+                return Ok(Response::with(status::NotModified));
+            }
         }
 
-        let net = match self.networks.get(network_name.to_owned()) {
-            None => return Ok(Response::with(status::BadRequest)),
-            Some(net) => net,
-        };
+        let depth = get_depth(req, net.context.epoch_stability_depth, self.max_depth)?;
 
-        match net.storage.read().unwrap().get_block_from_tag(&tag::HEAD) {
-            Err(Error::NoSuchTag) => Ok(Response::with((status::NotFound, "No Tip To Serve"))),
+        let storage = net.storage.read().unwrap();
+        let block = match storage.get_block_from_tag(&tag::HEAD) {
+            Err(Error::NoSuchTag) => {
+                return Err(common::Error::not_found("no-tip", "no tip to serve").into());
+            }
             Err(err) => {
                 error!("error while reading block: {:?}", err);
-                Ok(Response::with(status::InternalServerError))
+                return Err(common::Error::internal("error while reading the tip").into());
+            }
+            Ok(block) => block,
+        };
+        let head = block.header().compute_hash();
+        let block = if depth == 0 {
+            block
+        } else {
+            match chain::ancestor(&storage, &net.config.genesis_prev, &head, depth)
+                .map_err(common::Error::from)?
+            {
+                None => {
+                    return Err(common::Error::not_found(
+                        "no-block",
+                        format!("the chain has no block {} blocks before the tip", depth),
+                    )
+                    .into());
+                }
+                Some(hash) => chain::read_block(&storage, &hash)
+                    .and_then(|raw| raw.decode().map_err(chain::Error::from))
+                    .map_err(common::Error::from)?,
             }
-            Ok(block) => Ok(Response::with((
+        };
+
+        let mut response = if self.json_by_default && !common::accepts(req, CBOR) {
+            let point = BlockPoint::from_header(&block.header());
+            Response::with((
                 status::Ok,
-                block.header().to_raw().as_ref(),
-            ))),
-        }
+                serde_json::to_string(&point).unwrap(),
+                ContentType::json(),
+            ))
+        } else {
+            Response::with((status::Ok, block.header().to_raw().as_ref()))
+        };
+        // the HEAD may have moved since the snapshot of the request was
+        // taken (e.g. long-poll): report the one this answer is based on
+        response
+            .headers
+            .set_raw(snapshot::TIP_HEADER, vec![format!("{}", head).into_bytes()]);
+        Ok(response)
     }
 }
@@ -12,7 +12,7 @@ use serde_json;
 
 use super::super::config::Networks;
 use super::common;
-use exe_common::{config::net, network::Api, sync};
+use exe_common::{network::Api, sync};
 
 pub struct Handler {
     networks: Arc<Networks>,
@@ -36,42 +36,53 @@ impl iron::Handler for Handler {
             de.deserialize_complete().ok()
         }
         let mut req_body_str = String::new();
-        req.body.read_to_string(&mut req_body_str).unwrap();
+        if req.body.read_to_string(&mut req_body_str).is_err() {
+            return Err(common::Error::bad_request(
+                "invalid-transaction",
+                "Invalid input format for transaction",
+            )
+            .into());
+        }
         let txaux = match read_txaux_from_req_str(req_body_str.as_str()) {
             None => {
-                return Ok(Response::with((
-                    status::BadRequest,
+                return Err(common::Error::bad_request(
+                    "invalid-transaction",
                     "Invalid input format for transaction",
-                )));
+                )
+                .into());
             }
             Some(x) => x,
         };
 
-        let (net_name, net) = match common::get_network(req, &self.networks) {
-            None => {
-                return Ok(Response::with((status::BadRequest, "Invalid network name")));
-            }
-            Some(x) => x,
-        };
-        let netcfg_file = net.storage.read().unwrap().config.get_config_file();
-        let net_cfg = net::Config::from_file(&netcfg_file).expect("no network config present");
+        let (net_name, net) = common::get_network(req, &self.networks)?;
 
-        if let Err(verify_error) = txaux.verify(net_cfg.protocol_magic) {
-            return Ok(Response::with((
-                status::BadRequest,
+        if let Err(verify_error) = txaux.verify(net.context.protocol_magic) {
+            return Err(common::Error::bad_request(
+                "transaction-verification-failed",
                 format!("Transaction failed verification: {}", verify_error),
-            )));
+            )
+            .into());
         }
 
-        let mut peer = sync::get_peer(&net_name, &net_cfg, true);
+        let mut peer = sync::get_peer(&net_name, &net.config, true);
         match peer.send_transaction(txaux) {
             Err(e) => {
-                return Ok(Response::with((
-                    status::InternalServerError,
+                return Err(common::Error::new(
+                    status::BadGateway,
+                    "peer-error",
                     format!("Failed to send to peers: {}", e),
-                )));
+                )
+                .into());
+            }
+            Ok(false) => {
+                return Err(common::Error::new(
+                    status::BadGateway,
+                    "transaction-rejected",
+                    "Transaction rejected by the peers",
+                )
+                .into());
             }
-            Ok(value) => assert!(value),
+            Ok(true) => {}
         };
 
         Ok(Response::with((
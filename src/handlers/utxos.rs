@@ -1,6 +1,4 @@
-use cardano_storage::{chain_state, tag, Error};
-use exe_common::network::BlockRef;
-use exe_common::{genesisdata, sync};
+use cardano_storage::chain_state;
 
 use std::sync::Arc;
 
@@ -11,6 +9,7 @@ use iron::{IronResult, Request, Response};
 use router::Router;
 
 use super::super::config::Networks;
+use super::super::snapshot;
 use super::common;
 
 use std::str::FromStr;
@@ -37,46 +36,39 @@ struct Utxo {
 
 impl iron::Handler for Handler {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
-        let (_, net) = match common::get_network(req, &self.networks) {
-            None => {
-                return Ok(Response::with(status::BadRequest));
+        let (_, net) = common::get_network(req, &self.networks)?;
+
+        let address = common::get_param(req, "address")?;
+
+        let filter_address = match cardano::address::ExtendedAddr::from_str(&address) {
+            Ok(addr) => addr,
+            Err(_) => {
+                return Err(common::Error::bad_request(
+                    "invalid-address",
+                    format!("invalid address `{}'", address),
+                )
+                .into());
             }
-            Some(x) => x,
         };
 
-        let params = req.extensions.get::<router::Router>().unwrap();
-        let address = params.find("address").unwrap();
-
-        let genesis_str = genesisdata::data::get_genesis_data(&net.config.genesis_prev).unwrap();
-        let genesis_data = genesisdata::parse::parse(genesis_str.as_bytes());
+        let ref genesis_data = net.context.genesis_data;
 
         let storage = net.storage.read().unwrap();
 
-        let tip = match net.storage.read().unwrap().get_block_from_tag(&tag::HEAD) {
-            Err(Error::NoSuchTag) => {
-                return Ok(Response::with((status::NotFound, "No Tip To Serve")));
-            }
-            Err(err) => {
-                error!("error while reading block: {:?}", err);
-                return Ok(Response::with(status::InternalServerError));
-            }
-            Ok(block) => {
-                let header = block.header();
-                BlockRef {
-                    hash: header.compute_hash(),
-                    parent: header.previous_header(),
-                    date: header.blockdate(),
-                }
+        // the tip read when the request was received, or the one given
+        // with `?at=`
+        let tip = match snapshot::get_snapshot(req) {
+            None => {
+                return Err(common::Error::not_found("no-tip", "no tip to serve").into());
             }
+            Some(snapshot) => snapshot.tip,
         };
 
-        let chain_state =
-            chain_state::restore_chain_state(&storage, &genesis_data, &tip.hash).unwrap();
-
-        let filter_address = match cardano::address::ExtendedAddr::from_str(&address) {
-            Ok(addr) => addr,
-            Err(_) => return Ok(Response::with((status::BadRequest, "Invalid address"))),
-        };
+        let chain_state = chain_state::restore_chain_state(&storage, genesis_data, &tip.hash)
+            .map_err(|err| {
+                error!("error while restoring the chain state: {:?}", err);
+                common::Error::internal("error while restoring the chain state")
+            })?;
 
         let utxos = utxos_by_address(&chain_state.utxos, &filter_address);
 
@@ -0,0 +1,204 @@
+//! the addresses the HTTP server listens to
+//!
+//! Either a TCP socket address (IPv4 or IPv6, `0.0.0.0:80`, `[::1]:8080`)
+//! or, on unix, the path of a unix domain socket prefixed with `unix:`
+//! (`unix:/run/cardano-http-bridge.sock`).
+
+use super::tls::TlsServer;
+use iron;
+use std::{
+    fmt,
+    net::{SocketAddr, ToSocketAddrs},
+    path::PathBuf,
+    str::FromStr,
+};
+
+/// the prefix of the unix domain socket listen addresses
+pub const UNIX_PREFIX: &'static str = "unix:";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddress {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+#[derive(Debug)]
+pub struct InvalidListenAddress(String);
+impl fmt::Display for InvalidListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid listen address `{}'", self.0)
+    }
+}
+
+impl FromStr for ListenAddress {
+    type Err = InvalidListenAddress;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with(UNIX_PREFIX) {
+            let path = &s[UNIX_PREFIX.len()..];
+            if path.is_empty() || !cfg!(unix) {
+                return Err(InvalidListenAddress(s.to_string()));
+            }
+            return Ok(ListenAddress::Unix(PathBuf::from(path)));
+        }
+        // allow host names (`localhost:8080`) as well as IP addresses
+        match s.to_socket_addrs().ok().and_then(|mut addrs| addrs.next()) {
+            None => Err(InvalidListenAddress(s.to_string())),
+            Some(addr) => Ok(ListenAddress::Tcp(addr)),
+        }
+    }
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ListenAddress::Tcp(addr) => write!(f, "{}", addr),
+            ListenAddress::Unix(path) => write!(f, "{}{}", UNIX_PREFIX, path.display()),
+        }
+    }
+}
+
+impl ListenAddress {
+    /// start serving the handler on this address, over HTTPS if `tls` is set
+    ///
+    /// TLS is only available on TCP sockets: the unix domain sockets are
+    /// meant to be used by a local reverse proxy and always serve HTTP.
+    pub fn listen<H: iron::Handler>(
+        &self,
+        server: iron::Iron<H>,
+        tls: Option<&TlsServer>,
+    ) -> iron::error::HttpResult<iron::Listening> {
+        match self {
+            ListenAddress::Tcp(addr) => match tls {
+                None => server.http(addr),
+                Some(tls) => server.https(addr, tls.clone()),
+            },
+            #[cfg(unix)]
+            ListenAddress::Unix(path) => {
+                let listener = unix::UnixHttpListener::bind(path)?;
+                server.listen(listener, iron::Protocol::http())
+            }
+            #[cfg(not(unix))]
+            ListenAddress::Unix(_) => unreachable!(),
+        }
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use hyper;
+    use hyper::net::{NetworkListener, NetworkStream};
+    use std::{
+        fs,
+        io::{self, Read, Write},
+        net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+        os::unix::fs::FileTypeExt,
+        os::unix::net::{UnixListener, UnixStream},
+        path::Path,
+        sync::Arc,
+        time::Duration,
+    };
+
+    /// unix domain sockets have no IP address, the peers and the listener
+    /// are reported as localhost: they can only be on the same host.
+    fn localhost() -> SocketAddr {
+        SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))
+    }
+
+    #[derive(Clone)]
+    pub struct UnixHttpListener(Arc<UnixListener>);
+
+    impl UnixHttpListener {
+        pub fn bind(path: &Path) -> hyper::Result<Self> {
+            // remove the socket left over by a previous run of the bridge
+            if let Ok(metadata) = fs::symlink_metadata(path) {
+                if metadata.file_type().is_socket() {
+                    fs::remove_file(path)?;
+                }
+            }
+            Ok(UnixHttpListener(Arc::new(UnixListener::bind(path)?)))
+        }
+    }
+
+    impl NetworkListener for UnixHttpListener {
+        type Stream = UnixHttpStream;
+
+        fn accept(&mut self) -> hyper::Result<UnixHttpStream> {
+            let (stream, _) = self.0.accept()?;
+            Ok(UnixHttpStream(stream))
+        }
+
+        fn local_addr(&mut self) -> io::Result<SocketAddr> {
+            Ok(localhost())
+        }
+    }
+
+    pub struct UnixHttpStream(UnixStream);
+
+    impl Clone for UnixHttpStream {
+        fn clone(&self) -> Self {
+            UnixHttpStream(self.0.try_clone().expect("clone unix stream"))
+        }
+    }
+
+    impl io::Read for UnixHttpStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl io::Write for UnixHttpStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    impl NetworkStream for UnixHttpStream {
+        fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+            Ok(localhost())
+        }
+
+        fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+            self.0.set_read_timeout(dur)
+        }
+
+        fn set_write_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+            self.0.set_write_timeout(dur)
+        }
+
+        fn close(&mut self, how: ::std::net::Shutdown) -> io::Result<()> {
+            self.0.shutdown(how)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parse_listen_addresses() {
+        assert_eq!(
+            "0.0.0.0:80".parse::<ListenAddress>().unwrap(),
+            ListenAddress::Tcp("0.0.0.0:80".parse().unwrap())
+        );
+        assert_eq!(
+            "[::1]:8080".parse::<ListenAddress>().unwrap(),
+            ListenAddress::Tcp("[::1]:8080".parse().unwrap())
+        );
+        assert!("127.0.0.1".parse::<ListenAddress>().is_err());
+        assert!("unix:".parse::<ListenAddress>().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parse_unix_listen_address() {
+        assert_eq!(
+            "unix:/run/bridge.sock".parse::<ListenAddress>().unwrap(),
+            ListenAddress::Unix(PathBuf::from("/run/bridge.sock"))
+        );
+    }
+}
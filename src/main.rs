@@ -7,24 +7,53 @@ extern crate serde_yaml;
 extern crate log;
 extern crate env_logger;
 
+extern crate hyper;
+extern crate hyper_openssl;
 extern crate iron;
+extern crate openssl;
+extern crate route_recognizer;
 extern crate router;
 
+extern crate cryptoxide;
+#[cfg(unix)]
+extern crate signal_hook;
+
 extern crate cardano;
 extern crate cardano_storage;
 extern crate exe_common;
 
-use std::path::{
-    PathBuf,
-    Path,
-};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
+mod auth;
+mod chain;
 mod config;
+mod cors;
 mod handlers;
+mod listener;
+mod rate_limit;
 mod service;
+mod sighup;
+mod snapshot;
+mod tls;
+mod versions;
+mod webhooks;
+
+use self::config::{get_template, hermes_path, Config, ConfigFile, DEFAULT_PORT};
+use self::tls::TlsConfig;
 
-use self::config::{hermes_path, Config};
-use exe_common::config::net;
+/// panic if the name cannot name a network
+fn check_network_name(name: &str) {
+    if !handlers::common::validate_network_name(&name) {
+        panic!(
+            "invalid network name '{}': only letters, digits, '-' and '_' are allowed",
+            name
+        );
+    }
+    if handlers::common::is_reserved_network_name(name) {
+        panic!("'{}' is reserved by the API, it cannot name a network", name);
+    }
+}
 
 fn main() {
     use clap::{App, Arg, SubCommand};
@@ -36,14 +65,48 @@ fn main() {
         .subcommand(
             SubCommand::with_name("start")
                 .about("start explorer server")
+                .arg(
+                    Arg::with_name("CONFIG FILE")
+                        .long("config")
+                        .takes_value(true)
+                        .value_name("CONFIG FILE")
+                        .help("YAML configuration file of the bridge, the other options take precedence over its settings")
+                        .required(false),
+                )
                 .arg(
                     Arg::with_name("PORT NUMBER")
                         .long("port")
                         .takes_value(true)
                         .value_name("PORT NUMBER")
-                        .help("set the port number to listen to")
+                        .help("set the port number to listen to [default: 80]")
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("LISTEN ADDRESS")
+                        .long("listen")
+                        .takes_value(true)
+                        .value_name("LISTEN ADDRESS")
+                        .help("socket address to listen to (e.g. '127.0.0.1:8080', '[::]:8080' or 'unix:/path/to/socket'); may be given multiple times, takes precedence over --port")
+                        .required(false)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("TLS CERTIFICATE")
+                        .long("tls-cert")
+                        .takes_value(true)
+                        .value_name("TLS CERTIFICATE")
+                        .help("PEM file of the certificate to serve HTTPS with, reloaded on SIGHUP")
+                        .required(false)
+                        .requires("TLS KEY"),
+                )
+                .arg(
+                    Arg::with_name("TLS KEY")
+                        .long("tls-key")
+                        .takes_value(true)
+                        .value_name("TLS KEY")
+                        .help("PEM file of the private key of the TLS certificate")
                         .required(false)
-                        .default_value("80"),
+                        .requires("TLS CERTIFICATE"),
                 )
                 .arg(
                     Arg::with_name("NETWORKS DIRECTORY")
@@ -58,10 +121,26 @@ fn main() {
                         .long("template")
                         .takes_value(true)
                         .value_name("TEMPLATE")
-                        .help("either 'mainnet' or 'testnet'; may be given multiple times")
+                        .help("either 'mainnet', 'staging', 'testnet' or the path to a network configuration file (the network is then named after the file); may be given multiple times [default: mainnet]")
                         .required(false)
                         .multiple(true)
-                        .default_value("mainnet")
+                )
+                .arg(
+                    Arg::with_name("NETWORK")
+                        .long("network")
+                        .takes_value(true)
+                        .value_name("NAME=TEMPLATE")
+                        .help("serve the network of the given template (see --template) under the given name; may be given multiple times")
+                        .required(false)
+                        .multiple(true)
+                )
+                .arg(
+                    Arg::with_name("WEBHOOKS FILE")
+                        .long("webhooks")
+                        .takes_value(true)
+                        .value_name("WEBHOOKS FILE")
+                        .help("YAML file listing the webhooks to notify of the activity of addresses")
+                        .required(false),
                 )
                 .arg(
                     Arg::with_name("no-sync")
@@ -88,14 +167,26 @@ fn main() {
 
     match matches.subcommand() {
         ("start", Some(args)) => {
+            let file = match args.value_of("CONFIG FILE") {
+                None => ConfigFile::default(),
+                Some(path) => match ConfigFile::from_file(path) {
+                    Err(err) => panic!("unable to load configuration file '{}': {:?}", path, err),
+                    Ok(file) => file,
+                },
+            };
 
             // Determine the verbosity of logging:
             let arg_verbose = args.is_present("verbose");
             let arg_quiet   = args.is_present("quiet");
             let arg_silent  = args.is_present("silent");
 
+            let file_log_level = match file.get_log_level() {
+                Err(err) => panic!("{} in configuration file", err),
+                Ok(level) => level,
+            };
+
             let log_filter_level = match (arg_verbose, arg_quiet, arg_silent) {
-                (false, false, false) => {log::LevelFilter::Info}  // Default
+                (false, false, false) => {file_log_level}          // Default
                 (true , false, false) => {log::LevelFilter::Trace} // Verbose
                 (false, true , false) => {log::LevelFilter::Error} // Quiet
                 (false, false, true ) => {log::LevelFilter::Off}   // Silent
@@ -104,42 +195,124 @@ fn main() {
                              may be specified: --verbose --quiet --silent")}
             };
 
+            // the logger lets everything through, the level is set with
+            // `log::set_max_level` so it can be changed on SIGHUP
             env_logger::Builder::from_default_env()
-                .filter_level(log_filter_level)
+                .filter_level(log::LevelFilter::Trace)
                 .init();
+            log::set_max_level(log_filter_level);
 
-            let mut cfg = Config::new(
-                PathBuf::from(
-                    value_t!(args.value_of("NETWORKS DIRECTORY"), String).unwrap_or(
-                        hermes_path()
-                            .unwrap()
-                            .join("networks")
-                            .to_str()
-                            .unwrap()
-                            .to_string(),
-                    ),
-                ),
-                value_t!(args.value_of("PORT NUMBER"), u16).unwrap(),
-            );
+            let networks_dir = match value_t!(args.value_of("NETWORKS DIRECTORY"), String) {
+                Ok(dir) => PathBuf::from(dir),
+                Err(_) => file.networks_dir.clone().unwrap_or_else(|| {
+                    hermes_path().unwrap().join("networks")
+                }),
+            };
+            let port = match args.value_of("PORT NUMBER") {
+                None => file.port.unwrap_or(DEFAULT_PORT),
+                Some(_) => value_t!(args.value_of("PORT NUMBER"), u16).unwrap_or_else(|e| e.exit()),
+            };
+
+            let mut cfg = Config::new(networks_dir, port);
+            cfg.config_file = args.value_of("CONFIG FILE").map(PathBuf::from);
+            cfg.log_level_from_cli = arg_verbose || arg_quiet || arg_silent;
+            // an explicit port on the command line replaces the listen
+            // addresses of the configuration file
+            if let Some(addresses) = args.values_of("LISTEN ADDRESS") {
+                cfg.listen = addresses.map(|addr| addr.to_string()).collect();
+            } else if !args.is_present("PORT NUMBER") {
+                cfg.listen = file.listen.clone();
+            }
+            if let Err(err) = cfg.get_listen_addresses() {
+                panic!("{}", err);
+            }
+            cfg.tls = match (args.value_of("TLS CERTIFICATE"), args.value_of("TLS KEY")) {
+                (Some(cert), Some(key)) => Some(TlsConfig {
+                    cert: PathBuf::from(cert),
+                    key: PathBuf::from(key),
+                }),
+                _ => file.tls.clone(),
+            };
+            cfg.auth = file.auth.clone();
+            cfg.cors = file.cors.clone();
+            cfg.admin = file.admin.clone();
+            cfg.limits = file.limits.clone();
+            cfg.rate_limits = file.rate_limits.clone();
+            cfg.endpoints = file.endpoints.clone();
+            cfg.webhooks = file.webhooks.clone();
 
             ::std::fs::create_dir_all(cfg.root_dir.clone()).expect("create networks directory");
             info!("Created networks directory {:?}", cfg.root_dir);
 
-            for template in args.values_of("TEMPLATE").unwrap() {
-                let net_cfg = match template {
-                    "mainnet" => net::Config::mainnet(),
-                    "staging" => net::Config::staging(),
-                    "testnet" => net::Config::testnet(),
-                    filepath  => {
-                        let path = Path::new(filepath);
-                        match net::Config::from_file(path) {
+            let templates = args.values_of("TEMPLATE");
+            let named_networks = args.values_of("NETWORK");
+            match (templates, named_networks) {
+                (None, None) if !file.networks.is_empty() => {
+                    cfg.networks_from_file = true;
+                    for name in file.networks.keys() {
+                        check_network_name(name);
+                    }
+                    for (name, entry) in file.networks.iter() {
+                        cfg.add_network_entry(name, entry).unwrap_or_else(|err| {
+                            panic!("unable to add network '{}': {:?}", name, err)
+                        });
+                    }
+                }
+                (None, None) => {
+                    cfg.add_network("mainnet", &get_template("mainnet").unwrap()).unwrap();
+                }
+                (templates, named_networks) => {
+                    // a template given as a path is named after the file
+                    let templates = templates.into_iter().flatten().map(|template| {
+                        let name = match template {
+                            "mainnet" | "staging" | "testnet" => template.to_string(),
+                            path => Path::new(path)
+                                .file_stem()
+                                .map(|stem| stem.to_string_lossy().into_owned())
+                                .unwrap_or_else(|| path.to_string()),
+                        };
+                        (name, template)
+                    });
+                    let named_networks = named_networks.into_iter().flatten().map(|network| {
+                        let mut parts = network.splitn(2, '=');
+                        match (parts.next(), parts.next()) {
+                            (Some(name), Some(template)) => (name.to_string(), template),
+                            _ => panic!("invalid network '{}', expected NAME=TEMPLATE", network),
+                        }
+                    });
+                    // check every network before creating the storage of any
+                    let mut networks = BTreeMap::new();
+                    for (name, template) in templates.chain(named_networks) {
+                        let net_cfg = match get_template(template) {
                             None => panic!("unknown or missing template '{}'", template),
                             Some(cfg) => cfg,
+                        };
+                        if networks.contains_key(&name) {
+                            panic!("network '{}' is given more than once", name);
+                        }
+                        if !handlers::common::validate_network_name(&name.as_str()) {
+                            panic!(
+                                "invalid network name '{}' (template '{}'), use --network NAME={}",
+                                name, template, template
+                            );
                         }
+                        check_network_name(&name);
+                        networks.insert(name, net_cfg);
+                    }
+                    for (name, net_cfg) in networks.iter() {
+                        cfg.add_network(name, net_cfg).unwrap();
                     }
-                };
+                }
+            }
 
-                cfg.add_network(template, &net_cfg).unwrap();
+            if let Some(webhooks_file) = args.value_of("WEBHOOKS FILE") {
+                cfg.webhooks.clear();
+                cfg.add_webhooks(webhooks_file).expect("load webhooks file");
+            }
+            for webhook in cfg.webhooks.iter() {
+                if !cfg.network_names.contains(&webhook.network) {
+                    panic!("webhook {} watches unknown network '{}'", webhook.url, webhook.network);
+                }
             }
 
             cfg.sync = !args.is_present("no-sync");
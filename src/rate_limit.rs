@@ -0,0 +1,319 @@
+//! per client rate and concurrency limits
+//!
+//! Every client (identified by its API key if it presents one of the keys
+//! of the configuration, by its IP address otherwise) has a token bucket
+//! per kind of endpoint (see `handlers::common::get_endpoint`): each
+//! request takes a token, the bucket refills at `rate` tokens per second up
+//! to `burst` tokens. The number of requests of a client being served at
+//! the same time on a kind of endpoint may also be capped with
+//! `concurrency`.
+//!
+//! Requests over the limits are rejected with `429 Too Many Requests` and
+//! a `Retry-After` header.
+//!
+//! The clients without a key connecting through a unix socket, or through
+//! a reverse proxy running on the same host, are all seen with the IP
+//! address `127.0.0.1`: they share the same buckets.
+
+use iron::headers::ContentType;
+use iron::status;
+use iron::{AroundMiddleware, Handler, IronResult, Request, Response};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::auth;
+use super::handlers::common;
+
+/// above this number of buckets, the idle full buckets are dropped
+const MAX_IDLE_BUCKETS: usize = 10_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    /// number of requests per second
+    pub rate: f64,
+    /// number of requests which can be made at once after an idle period
+    pub burst: u32,
+    /// maximum number of requests served at the same time
+    #[serde(default)]
+    pub concurrency: Option<usize>,
+}
+
+/// the limits per client, the clients without a key behind a unix socket
+/// or a local reverse proxy all count as one (see the module documentation)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// the limits of the kinds of endpoints not listed in `routes`,
+    /// unlimited if not set
+    #[serde(default)]
+    pub default: Option<RateLimit>,
+    /// the limits per kind of endpoint (`utxos', `chain-state', `tip'...)
+    #[serde(default)]
+    pub routes: BTreeMap<String, RateLimit>,
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+    in_flight: usize,
+}
+
+struct Buckets {
+    buckets: HashMap<(String, String), Bucket>,
+    /// the idle buckets are dropped when there are more buckets than this
+    sweep_at: usize,
+}
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    /// the API keys of the configuration: any other key a client sends is
+    /// not trusted to identify it
+    keys: HashSet<String>,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig, keys: HashSet<String>) -> Self {
+        RateLimiter {
+            config: config,
+            keys: keys,
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                sweep_at: MAX_IDLE_BUCKETS,
+            }),
+        }
+    }
+
+    /// the name of the bucket of a client: its key if it is known, its IP
+    /// address otherwise
+    fn client(&self, key: Option<String>, ip: String) -> String {
+        match key {
+            Some(key) if self.keys.contains(&key) => key,
+            _ => ip,
+        }
+    }
+
+    /// drop the buckets which are not in use and would be full by now
+    fn sweep(&self, buckets: &mut Buckets, now: Instant) {
+        let config = &self.config;
+        buckets.buckets.retain(|(route, _), bucket| {
+            let limit = match config.routes.get(route).or(config.default.as_ref()) {
+                None => return false,
+                Some(limit) => limit,
+            };
+            let elapsed = now.duration_since(bucket.last_refill);
+            let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+            bucket.in_flight > 0 || bucket.tokens + elapsed * limit.rate < limit.burst as f64
+        });
+        // scan again only once the number of buckets doubled, so the cost
+        // of the sweeps is spread over the requests
+        buckets.sweep_at = ::std::cmp::max(MAX_IDLE_BUCKETS, 2 * buckets.buckets.len());
+    }
+
+    fn get_limit(&self, route: &str) -> Option<&RateLimit> {
+        self.config
+            .routes
+            .get(route)
+            .or(self.config.default.as_ref())
+    }
+
+    /// take a token of the client's bucket for the route
+    ///
+    /// On success the request is counted as in flight until `release` is
+    /// called. Otherwise returns how long the client should wait before
+    /// retrying.
+    pub fn acquire(&self, route: &str, client: &str, now: Instant) -> Result<(), Duration> {
+        let limit = match self.get_limit(route) {
+            None => return Ok(()),
+            Some(limit) => limit,
+        };
+        let burst = limit.burst as f64;
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.buckets.len() > buckets.sweep_at {
+            self.sweep(&mut buckets, now);
+        }
+        let bucket = buckets
+            .buckets
+            .entry((route.to_string(), client.to_string()))
+            .or_insert(Bucket {
+                tokens: burst,
+                last_refill: now,
+                in_flight: 0,
+            });
+
+        let elapsed = now.duration_since(bucket.last_refill);
+        let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+        bucket.tokens = (bucket.tokens + elapsed * limit.rate).min(burst);
+        bucket.last_refill = now;
+
+        if let Some(concurrency) = limit.concurrency {
+            if bucket.in_flight >= concurrency {
+                return Err(Duration::from_secs(1));
+            }
+        }
+        if bucket.tokens < 1.0 {
+            let wait = if limit.rate > 0.0 {
+                ((1.0 - bucket.tokens) / limit.rate).ceil() as u64
+            } else {
+                60
+            };
+            return Err(Duration::from_secs(::std::cmp::max(1, wait)));
+        }
+
+        bucket.tokens -= 1.0;
+        bucket.in_flight += 1;
+        Ok(())
+    }
+
+    /// mark a request accepted by `acquire` as done
+    pub fn release(&self, route: &str, client: &str) {
+        let mut buckets = self.buckets.lock().unwrap();
+        if let Some(bucket) = buckets.buckets.get_mut(&(route.to_string(), client.to_string())) {
+            bucket.in_flight = bucket.in_flight.saturating_sub(1);
+        }
+    }
+}
+
+struct InFlight<'a> {
+    limiter: &'a RateLimiter,
+    route: &'a str,
+    client: &'a str,
+}
+impl<'a> Drop for InFlight<'a> {
+    fn drop(&mut self) {
+        self.limiter.release(self.route, self.client);
+    }
+}
+
+struct Limited {
+    limiter: RateLimiter,
+    handler: Box<dyn Handler>,
+}
+
+impl Handler for Limited {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let route = common::get_endpoint(req)
+            .map(|(_, endpoint)| endpoint)
+            .unwrap_or_default();
+        let client = self
+            .limiter
+            .client(auth::get_api_key(req), req.remote_addr.ip().to_string());
+
+        match self.limiter.acquire(&route, &client, Instant::now()) {
+            Err(retry_after) => {
+                info!("rate limited {} on {}", client, route);
+                let error = common::Error::new(
+                    status::TooManyRequests,
+                    "too-many-requests",
+                    format!("too many requests, retry in {}s", retry_after.as_secs()),
+                );
+                let mut response =
+                    Response::with((error.status, error.to_json(), ContentType::json()));
+                response.headers.set_raw(
+                    "Retry-After",
+                    vec![format!("{}", retry_after.as_secs()).into_bytes()],
+                );
+                Ok(response)
+            }
+            Ok(()) => {
+                // released even if the handler panics
+                let _in_flight = InFlight {
+                    limiter: &self.limiter,
+                    route: &route,
+                    client: &client,
+                };
+                self.handler.handle(req)
+            }
+        }
+    }
+}
+
+impl AroundMiddleware for RateLimiter {
+    fn around(self, handler: Box<dyn Handler>) -> Box<dyn Handler> {
+        Box::new(Limited {
+            limiter: self,
+            handler: handler,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn limiter(concurrency: Option<usize>) -> RateLimiter {
+        let mut routes = BTreeMap::new();
+        routes.insert(
+            "utxos".to_string(),
+            RateLimit {
+                rate: 1.0,
+                burst: 2,
+                concurrency: concurrency,
+            },
+        );
+        let mut keys = HashSet::new();
+        keys.insert("secret".to_string());
+        RateLimiter::new(
+            RateLimitConfig {
+                default: None,
+                routes: routes,
+            },
+            keys,
+        )
+    }
+
+    #[test]
+    fn burst_then_refill() {
+        let limiter = limiter(None);
+        let now = Instant::now();
+        assert_eq!(limiter.acquire("utxos", "client", now), Ok(()));
+        assert_eq!(limiter.acquire("utxos", "client", now), Ok(()));
+        assert_eq!(
+            limiter.acquire("utxos", "client", now),
+            Err(Duration::from_secs(1))
+        );
+        // the buckets are per client and per route
+        assert_eq!(limiter.acquire("utxos", "other", now), Ok(()));
+        assert_eq!(limiter.acquire("tip", "client", now), Ok(()));
+
+        let later = now + Duration::from_secs(1);
+        assert_eq!(limiter.acquire("utxos", "client", later), Ok(()));
+    }
+
+    #[test]
+    fn concurrency() {
+        let limiter = limiter(Some(1));
+        let now = Instant::now();
+        assert_eq!(limiter.acquire("utxos", "client", now), Ok(()));
+        assert!(limiter.acquire("utxos", "client", now).is_err());
+        limiter.release("utxos", "client");
+        assert_eq!(limiter.acquire("utxos", "client", now), Ok(()));
+    }
+
+    #[test]
+    fn only_known_keys_identify_clients() {
+        let limiter = limiter(None);
+        let ip = "192.0.2.1".to_string();
+        assert_eq!(limiter.client(Some("secret".to_string()), ip.clone()), "secret");
+        assert_eq!(limiter.client(Some("random".to_string()), ip.clone()), ip);
+        assert_eq!(limiter.client(None, ip.clone()), ip);
+    }
+
+    #[test]
+    fn idle_buckets_are_dropped() {
+        let limiter = limiter(None);
+        let now = Instant::now();
+        for client in 0..=MAX_IDLE_BUCKETS {
+            assert_eq!(limiter.acquire("utxos", &client.to_string(), now), Ok(()));
+            limiter.release("utxos", &client.to_string());
+        }
+        // the buckets are full again 1s later
+        let later = now + Duration::from_secs(1);
+        assert_eq!(limiter.acquire("utxos", "client", later), Ok(()));
+        assert_eq!(limiter.buckets.lock().unwrap().buckets.len(), 1);
+    }
+}
@@ -1,10 +1,20 @@
-use super::config::{Config, Network, Networks};
+use super::auth::Auth;
+use super::chain;
+use super::cors::Cors;
+use super::config::{Config, ConfigFile, Network, NetworkState, Networks};
 use super::handlers;
+use super::rate_limit::RateLimiter;
+use super::sighup;
+use super::snapshot::Snapshots;
+use super::tls::TlsServer;
+use super::versions::{ApiVersion, StripVersion, VersionRouter};
+use super::webhooks::Notifier;
 use exe_common::config::net;
-use exe_common::{genesisdata, sync};
+use exe_common::sync;
 use iron;
+use iron::{AroundMiddleware, Handler};
 use router::Router;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
@@ -13,70 +23,386 @@ pub fn start(cfg: Config) {
         Err(err) => panic!("Unable to get networks: {:?}", err),
         Ok(nets) => nets,
     });
-    let _refreshers = if cfg.sync {
-        Some(start_networks_refreshers(networks.clone()))
-    } else {
-        None
-    };
-    let _server = start_http_server(&cfg, networks);
+    let notifier = Notifier::new(cfg.webhooks.clone(), cfg.limits.max_fork_depth);
+    let notifier = Arc::new(match notifier {
+        Err(err) => panic!("Unable to set up webhooks: {}", err),
+        Ok(notifier) => notifier,
+    });
+    let tls = cfg.tls.clone().map(|tls| match TlsServer::new(tls) {
+        Err(err) => panic!("Unable to load the TLS certificate: {}", err),
+        Ok(tls) => tls,
+    });
+    let _refreshers = start_networks_refreshers(networks.clone(), notifier.clone());
+
+    let shared_cfg = Arc::new(Mutex::new(cfg.clone()));
+    let admin = cfg.admin.as_ref().map(|admin| {
+        handlers::admin::Handler::new(
+            networks.clone(),
+            shared_cfg.clone(),
+            notifier.clone(),
+            admin.key.clone(),
+        )
+    });
+    let _server = start_http_server(&cfg, networks.clone(), admin, tls.as_ref());
+
+    let mut sighup_hooks: Vec<sighup::Hook> = vec![];
+    sighup_hooks.push(Box::new(move || reload_config(&shared_cfg, &networks, &notifier)));
+    if let Some(tls) = tls {
+        sighup_hooks.push(Box::new(move || match tls.reload() {
+            Err(err) => error!("Unable to reload the TLS certificate: {}", err),
+            Ok(()) => info!("TLS certificate reloaded"),
+        }));
+    }
+    sighup::spawn(sighup_hooks).expect("install SIGHUP handler");
 
     // XXX: consider installing a signal handler to initiate a graceful shutdown here
     // XXX: after initiating shutdown, do `refresher.join()` and something similar for `server`.
 }
 
-fn start_http_server(cfg: &Config, networks: Arc<Networks>) -> iron::Listening {
+fn start_http_server(
+    cfg: &Config,
+    networks: Arc<Networks>,
+    admin: Option<handlers::admin::Handler>,
+    tls: Option<&TlsServer>,
+) -> Vec<iron::Listening> {
+    let long_polls = Arc::new(handlers::tip::LongPolls::new(cfg.limits.max_long_polls));
+    let router = VersionRouter::new(
+        api_router(cfg, networks.clone(), admin.clone(), long_polls.clone(), ApiVersion::V1),
+        api_router(cfg, networks.clone(), admin, long_polls, ApiVersion::V2),
+    );
+
+    let mut chain = iron::Chain::new(router);
+    chain.link_after(handlers::common::NoRouteJson);
+    chain.link_around(Snapshots::new(networks.clone()));
+    if let Some(ref auth) = cfg.auth {
+        chain.link_before(Auth::new(auth.clone()));
+    }
+    if let Some(ref rate_limits) = cfg.rate_limits {
+        let keys = cfg
+            .auth
+            .iter()
+            .flat_map(|auth| auth.keys.iter().map(|key| key.key.clone()))
+            .collect();
+        chain.link_around(RateLimiter::new(rate_limits.clone(), keys));
+    }
+
+    // CORS wraps the whole chain: the preflight requests are answered
+    // before the authentication
+    let handler: Box<dyn Handler> = match cfg.cors {
+        None => Box::new(chain),
+        Some(ref cors) => Cors::new(cors.clone()).around(Box::new(chain)),
+    };
+    // and the version prefix is removed before anything else
+    let handler = StripVersion.around(handler);
+
+    // the same handler serves every listen address
+    let handler = Arc::new(handler);
+    cfg.get_listen_addresses()
+        .expect("valid listen addresses")
+        .iter()
+        .map(|addr| {
+            let handler = handler.clone();
+            let mut server = iron::Iron::new(move |req: &mut iron::Request| handler.handle(req));
+            if let Some(threads) = cfg.limits.http_threads {
+                server.threads = threads;
+            }
+            info!(
+                "listening to {} ({})",
+                addr,
+                if tls.is_some() { "https" } else { "http" }
+            );
+            addr.listen(server, tls).expect("start http server")
+        })
+        .collect()
+}
+
+/// the routes of the given version of the API
+fn api_router(
+    cfg: &Config,
+    networks: Arc<Networks>,
+    admin: Option<handlers::admin::Handler>,
+    long_polls: Arc<handlers::tip::LongPolls>,
+    version: ApiVersion,
+) -> Router {
     let mut router = Router::new();
-    handlers::block::Handler::new(networks.clone()).route(&mut router);
-    handlers::genesis::Handler::new(networks.clone()).route(&mut router);
-    handlers::pack::Handler::new(networks.clone()).route(&mut router);
-    handlers::epoch::Handler::new(networks.clone()).route(&mut router);
-    handlers::tip::Handler::new(networks.clone()).route(&mut router);
-    handlers::tx::Handler::new(networks.clone()).route(&mut router);
-    handlers::chain_state::Handler::new(networks.clone()).route(&mut router);
-    handlers::chain_state_delta::Handler::new(networks.clone()).route(&mut router);
-    handlers::utxos::Handler::new(networks.clone()).route(&mut router);
-    info!("listening to port {}", cfg.port);
-    iron::Iron::new(router)
-        .http(format!("0.0.0.0:{}", cfg.port))
-        .expect("start http server")
+    if cfg.is_endpoint_enabled("networks") {
+        handlers::networks::Handler::new(networks.clone()).route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("config") {
+        handlers::config::Handler::new(networks.clone()).route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("block") {
+        handlers::block::Handler::new(networks.clone()).route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("genesis_by_hash") {
+        handlers::genesis::Handler::new(networks.clone()).route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("genesis") {
+        handlers::genesis::NetworkHandler::new(networks.clone()).route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("genesis_info") {
+        handlers::genesis::InfoHandler::new(networks.clone()).route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("genesis_avvm") {
+        handlers::genesis::AvvmHandler::new(networks.clone()).route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("genesis_balance") {
+        handlers::genesis::BalanceHandler::new(networks.clone()).route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("pack") {
+        handlers::pack::Handler::new(networks.clone()).route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("epoch") {
+        handlers::epoch::Handler::new(networks.clone()).route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("tip") {
+        let tip = handlers::tip::Handler::new(
+            networks.clone(),
+            cfg.limits.max_long_poll_timeout,
+            long_polls,
+            cfg.limits.max_tip_depth,
+        );
+        match version {
+            ApiVersion::V1 => tip.route(&mut router),
+            ApiVersion::V2 => tip.json_by_default().route(&mut router),
+        };
+    }
+    if cfg.is_endpoint_enabled("time_slot") {
+        handlers::time::SlotHandler::new(networks.clone()).route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("time_at") {
+        handlers::time::AtHandler::new(networks.clone()).route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("block_at_time") {
+        handlers::time::BlockHandler::new(networks.clone()).route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("txs_signed_send") {
+        handlers::tx::Handler::new(networks.clone()).route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("chain-state") {
+        handlers::chain_state::Handler::new(networks.clone()).route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("chain-state-delta") {
+        handlers::chain_state_delta::Handler::new(networks.clone()).route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("utxos") {
+        handlers::utxos::Handler::new(networks.clone()).route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("follow") {
+        handlers::follow::Handler::new(
+            networks.clone(),
+            cfg.limits.max_follow_count,
+            cfg.limits.max_fork_depth,
+        )
+        .route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("fork") {
+        handlers::fork::Handler::new(networks.clone(), cfg.limits.max_fork_depth)
+            .route(&mut router);
+    }
+    let admin_enabled = admin.is_some() && cfg.is_endpoint_enabled("admin");
+    if let (Some(admin), true) = (admin, admin_enabled) {
+        admin.route(&mut router);
+    }
+
+    let endpoints: Vec<_> = handlers::openapi::endpoints(version)
+        .into_iter()
+        .filter(|endpoint| {
+            if endpoint.name.starts_with("admin_") {
+                admin_enabled
+            } else {
+                cfg.is_endpoint_enabled(endpoint.name)
+            }
+        })
+        .collect();
+    if cfg.is_endpoint_enabled("openapi") {
+        handlers::openapi::Handler::new(&endpoints, version).route(&mut router);
+    }
+    if cfg.is_endpoint_enabled("index") {
+        handlers::index::Handler::new(networks.clone(), endpoints, version).route(&mut router);
+    }
+    router
 }
 
 // TODO: make this a struct which receives a shutdown message on a channel and then wraps itself up
-fn start_networks_refreshers(networks: Arc<Networks>) -> Vec<thread::JoinHandle<()>> {
-    let mut threads = vec![];
-    for (label, net) in networks.iter() {
-        let label = label.to_owned();
-        let mut net = net.clone();
-        threads.push(thread::spawn(move || {
-            loop {
-                refresh_network(&label, &mut net);
-                // In case of an error, wait a while before retrying.
-                thread::sleep(Duration::from_secs(10));
+fn start_networks_refreshers(
+    networks: Arc<Networks>,
+    notifier: Arc<Notifier>,
+) -> Vec<thread::JoinHandle<()>> {
+    networks
+        .to_vec()
+        .into_iter()
+        .filter(|(_, net)| net.sync)
+        .map(|(label, net)| spawn_refresher(label, net, notifier.clone()))
+        .collect()
+}
+
+/// keep the network synchronized until it is removed
+pub fn spawn_refresher(
+    label: String,
+    mut net: Network,
+    notifier: Arc<Notifier>,
+) -> thread::JoinHandle<()> {
+    net.state.set_refreshing(true);
+    thread::spawn(move || {
+        // cleared even if the refresher panics
+        let _refreshing = Refreshing(net.state.clone());
+        loop {
+            if net.state.is_removed() {
+                info!("Network {:?} removed, stopping its refresher", label);
+                break;
             }
-        }));
+            if !net.state.is_paused() {
+                refresh_network(&label, &mut net, &notifier);
+            }
+            // In case of an error, wait a while before retrying.
+            thread::sleep(Duration::from_secs(10));
+        }
+    })
+}
+
+/// mark the refresher of a network as stopped once dropped
+struct Refreshing(Arc<NetworkState>);
+impl Drop for Refreshing {
+    fn drop(&mut self) {
+        self.0.set_refreshing(false);
+    }
+}
+
+/// apply the changes of the configuration file and of the networks'
+/// `config.yml`
+///
+/// The networks listed in the configuration file are added and removed as
+/// the file changes (unless the networks were given on the command line),
+/// the new peers of the networks already served are used from the next
+/// synchronization round.
+fn reload_config(cfg: &Mutex<Config>, networks: &Networks, notifier: &Arc<Notifier>) {
+    let mut cfg = cfg.lock().unwrap();
+
+    let file = match cfg.config_file {
+        None => None,
+        Some(ref path) => match ConfigFile::from_file(path) {
+            Err(err) => {
+                error!("Unable to reload the configuration file {:?}: {:?}", path, err);
+                None
+            }
+            Ok(file) => Some(file),
+        },
+    };
+
+    if let Some(file) = file {
+        if !cfg.log_level_from_cli {
+            match file.get_log_level() {
+                Err(err) => error!("Unable to reload the log level: {}", err),
+                Ok(level) => log::set_max_level(level),
+            }
+        }
+
+        if cfg.networks_from_file {
+            let removed: Vec<_> = cfg
+                .file_networks
+                .iter()
+                .filter(|name| !file.networks.contains_key(*name))
+                .cloned()
+                .collect();
+            for name in removed {
+                cfg.remove_network(&name);
+                networks.remove(&name);
+                info!("Network {:?} removed from the configuration", name);
+            }
+
+            for (name, entry) in file.networks.iter() {
+                if networks.contains(name) {
+                    if let Some(ref peers) = entry.peers {
+                        cfg.set_network_peers(name, peers.clone())
+                            .unwrap_or_else(|err| {
+                                error!("Unable to update the peers of {:?}: {:?}", name, err)
+                            });
+                    }
+                    continue;
+                }
+                // the refresher of a network removed by a previous reload
+                // would write to the same storage as the new one
+                if networks.is_stopping(name) {
+                    error!(
+                        "Network {:?} is still stopping, reload the configuration again to add it",
+                        name
+                    );
+                    continue;
+                }
+                if !handlers::common::validate_network_name(&name.as_str())
+                    || handlers::common::is_reserved_network_name(name)
+                {
+                    error!("Invalid network name {:?}", name);
+                    continue;
+                }
+                let net = match cfg
+                    .add_network_entry(name, entry)
+                    .and_then(|()| cfg.get_network(name))
+                {
+                    Err(err) => {
+                        error!("Unable to add network {:?}: {:?}", name, err);
+                        continue;
+                    }
+                    Ok(net) => net,
+                };
+                if !networks.insert(name.clone(), net.clone()) {
+                    error!("Network {:?} is already served", name);
+                    continue;
+                }
+                info!("Network {:?} added from the configuration", name);
+                if net.sync {
+                    spawn_refresher(name.clone(), net, notifier.clone());
+                }
+            }
+        }
+    }
+
+    for name in networks.names() {
+        match cfg.get_network_config(&name) {
+            Err(err) => error!("Unable to reload the configuration of {:?}: {:?}", name, err),
+            Ok(netcfg) => networks.set_config(&name, netcfg),
+        }
     }
-    threads
 }
 
 // XXX: how do we want to report partial failures?
-fn refresh_network(label: &str, net: &mut Network) {
+fn refresh_network(label: &str, net: &mut Network, notifier: &Notifier) {
     info!("Refreshing network {:?}", label);
 
     let netcfg_file = net.storage.read().unwrap().config.get_config_file();
     let net_cfg = net::Config::from_file(&netcfg_file).expect("no network config present");
 
-    let genesis_data = {
-        let genesis_data = genesisdata::data::get_genesis_data(&net_cfg.genesis_prev)
-            .expect("genesis data not found");
-        genesisdata::parse::parse(genesis_data.as_bytes())
-    };
+    let ref genesis_data = net.context.genesis_data;
+
+    let previous_tip = chain::get_tip(&net.storage.read().unwrap()).ok();
 
     sync::net_sync(
         &mut sync::get_peer(&label, &net_cfg, true),
         &net_cfg,
-        &genesis_data,
+        genesis_data,
         net.storage.clone(),
         false,
     )
     .unwrap_or_else(|err| warn!("Sync failed: {:?}", err));
+
+    // on the very first sync there is nothing to compare to: do not notify
+    // the webhooks of the whole history of the chain
+    if let (true, Some(previous_tip)) = (notifier.watches(label), previous_tip) {
+        let storage = net.storage.read().unwrap();
+        match chain::get_tip(&storage) {
+            Ok(ref tip) if tip.hash != previous_tip.hash => notifier
+                .notify_blocks(
+                    label,
+                    &storage,
+                    genesis_data,
+                    &net_cfg.genesis_prev,
+                    &previous_tip.hash,
+                    &tip.hash,
+                )
+                .unwrap_or_else(|err| warn!("Webhooks notification failed: {}", err)),
+            Ok(_) => {}
+            Err(err) => warn!("Unable to read the new tip: {}", err),
+        }
+    }
 }
@@ -0,0 +1,37 @@
+//! actions to run when the bridge receives SIGHUP
+//!
+//! `SIGHUP` is the conventional way to ask a daemon to reload its
+//! configuration. Every hook registered here is called, in order, each
+//! time the signal is received. On platforms without signals this is a
+//! no-op.
+
+use std::io;
+
+pub type Hook = Box<dyn Fn() + Send>;
+
+/// start the thread running the hooks on SIGHUP
+#[cfg(unix)]
+pub fn spawn(hooks: Vec<Hook>) -> io::Result<()> {
+    use signal_hook::{iterator::Signals, SIGHUP};
+    use std::thread;
+
+    if hooks.is_empty() {
+        return Ok(());
+    }
+
+    let signals = Signals::new(&[SIGHUP])?;
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            info!("SIGHUP received, reloading");
+            for hook in hooks.iter() {
+                hook();
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn spawn(_hooks: Vec<Hook>) -> io::Result<()> {
+    Ok(())
+}
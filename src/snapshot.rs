@@ -0,0 +1,124 @@
+//! pinning the requests to a state of the chain
+//!
+//! The tip of the network is read once when a request is received and
+//! returned in the `X-Cardano-Tip` header of the response. The endpoints
+//! depending on the state of the chain (see `PINNABLE`) answer for that
+//! tip, or for the block given with `?at=<hash>` so a client can make
+//! several requests against the same state while the chain moves on.
+
+use std::sync::Arc;
+
+use iron::typemap::Key;
+use iron::{AroundMiddleware, Handler, IronResult, Request, Response};
+
+use super::chain::{self, BlockPoint};
+use super::config::Networks;
+use super::handlers::common;
+
+pub const TIP_HEADER: &'static str = "X-Cardano-Tip";
+
+/// the kinds of endpoints accepting `?at=<hash>`
+const PINNABLE: [&'static str; 3] = ["utxos", "chain-state", "genesis"];
+
+/// the state of the chain a request is answered for
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub tip: BlockPoint,
+    /// the tip was given by the client with `?at=`
+    pub pinned: bool,
+}
+
+impl Key for Snapshot {
+    type Value = Snapshot;
+}
+
+/// the snapshot of the request, `None` if it is not about a network or if
+/// the network has no tip yet
+pub fn get_snapshot(req: &Request) -> Option<Snapshot> {
+    req.extensions.get::<Snapshot>().cloned()
+}
+
+pub struct Snapshots {
+    networks: Arc<Networks>,
+}
+
+impl Snapshots {
+    pub fn new(networks: Arc<Networks>) -> Self {
+        Snapshots { networks: networks }
+    }
+
+    fn take(&self, req: &Request) -> common::Result<Option<Snapshot>> {
+        let (network, endpoint) = match common::get_endpoint(req) {
+            None => return Ok(None),
+            Some(endpoint) => endpoint,
+        };
+        let net = match self.networks.get(&network) {
+            None => return Ok(None),
+            Some(net) => net,
+        };
+        let storage = net.storage.read().unwrap();
+
+        let at = common::get_query_param(req, "at")
+            .filter(|_| PINNABLE.contains(&endpoint.as_str()));
+        match at {
+            None => match chain::get_tip(&storage) {
+                Err(chain::Error::NoTip) => Ok(None),
+                Err(err) => Err(err.into()),
+                Ok(tip) => Ok(Some(Snapshot {
+                    tip: tip,
+                    pinned: false,
+                })),
+            },
+            Some(at) => {
+                let hash = common::parse_hash("at", &at)?;
+                Ok(Some(Snapshot {
+                    tip: chain::read_point(&storage, &hash)?,
+                    pinned: true,
+                }))
+            }
+        }
+    }
+}
+
+struct SnapshotHandler {
+    snapshots: Snapshots,
+    handler: Box<dyn Handler>,
+}
+
+impl Handler for SnapshotHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let snapshot = match self.snapshots.take(req)? {
+            None => return self.handler.handle(req),
+            Some(snapshot) => snapshot,
+        };
+        let header = vec![format!("{}", snapshot.tip.hash).into_bytes()];
+        req.extensions.insert::<Snapshot>(snapshot);
+
+        // the handlers reading the HEAD again (`tip`) set the header
+        // themselves
+        let set_header = |response: &mut Response| {
+            if response.headers.get_raw(TIP_HEADER).is_none() {
+                response.headers.set_raw(TIP_HEADER, header.clone());
+            }
+        };
+        match self.handler.handle(req) {
+            Ok(mut response) => {
+                set_header(&mut response);
+                Ok(response)
+            }
+            Err(mut error) => {
+                set_header(&mut error.response);
+                Err(error)
+            }
+        }
+    }
+}
+
+impl AroundMiddleware for Snapshots {
+    fn around(self, handler: Box<dyn Handler>) -> Box<dyn Handler> {
+        Box::new(SnapshotHandler {
+            snapshots: self,
+            handler: handler,
+        })
+    }
+}
@@ -0,0 +1,56 @@
+//! HTTPS termination
+//!
+//! The certificate and the private key are read from PEM files. They can be
+//! replaced on disk and reloaded without restarting the bridge: the new
+//! certificate is used for the connections accepted after the reload.
+
+use hyper;
+use hyper::net::{HttpStream, SslServer};
+use hyper_openssl::OpensslServer;
+use openssl::error::ErrorStack;
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// the PEM encoded certificate (chain) of the server
+    pub cert: PathBuf,
+    /// the PEM encoded private key of the certificate
+    pub key: PathBuf,
+}
+
+#[derive(Clone)]
+pub struct TlsServer {
+    config: TlsConfig,
+    server: Arc<RwLock<OpensslServer>>,
+}
+
+impl TlsServer {
+    pub fn new(config: TlsConfig) -> Result<Self, ErrorStack> {
+        let server = OpensslServer::from_files(&config.key, &config.cert)?;
+        Ok(TlsServer {
+            config: config,
+            server: Arc::new(RwLock::new(server)),
+        })
+    }
+
+    /// read the certificate and the private key again
+    ///
+    /// on error the previous certificate is kept.
+    pub fn reload(&self) -> Result<(), ErrorStack> {
+        let server = OpensslServer::from_files(&self.config.key, &self.config.cert)?;
+        *self.server.write().unwrap() = server;
+        Ok(())
+    }
+}
+
+impl SslServer for TlsServer {
+    type Stream = <OpensslServer as SslServer>::Stream;
+
+    fn wrap_server(&self, stream: HttpStream) -> hyper::Result<Self::Stream> {
+        self.server.read().unwrap().wrap_server(stream)
+    }
+}
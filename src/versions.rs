@@ -0,0 +1,155 @@
+//! versioned API namespaces
+//!
+//! The routes are served under `/v1/` and `/v2/`. The unversioned paths
+//! (`/:network/tip`...) are kept as aliases of `/v1/` for the existing
+//! clients. `/v1/` never changes behaviour, the breaking improvements go
+//! to `/v2/`.
+//!
+//! The version prefix is stripped from the URL as soon as the request is
+//! received (see `StripVersion`) so the middlewares (authentication, rate
+//! limits, CORS) see the same paths whatever the version, and the version
+//! is recorded in the request's extensions for `VersionRouter` to pick the
+//! router of the version.
+
+use iron::typemap::Key;
+use iron::{AroundMiddleware, Handler, IronResult, Request, Response};
+use router::Router;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    V1,
+    V2,
+}
+
+impl ApiVersion {
+    pub const ALL: [ApiVersion; 2] = [ApiVersion::V1, ApiVersion::V2];
+
+    /// the path segment of the version
+    pub fn prefix(self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "v1",
+            ApiVersion::V2 => "v2",
+        }
+    }
+
+    fn from_prefix(segment: &str) -> Option<Self> {
+        ApiVersion::ALL
+            .iter()
+            .cloned()
+            .find(|version| version.prefix() == segment)
+    }
+}
+
+impl Key for ApiVersion {
+    type Value = ApiVersion;
+}
+
+/// the version of the API the request is for
+pub fn get_version(req: &Request) -> ApiVersion {
+    req.extensions
+        .get::<ApiVersion>()
+        .cloned()
+        .unwrap_or(ApiVersion::V1)
+}
+
+/// the segments of the path without the empty ones (`//mainnet/tip/`)
+/// and the version they start with, if any
+fn split_path(path: &[&str]) -> (Option<ApiVersion>, Vec<String>) {
+    let mut segments = path
+        .iter()
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| segment.to_string())
+        .collect::<Vec<_>>();
+    let version = segments
+        .first()
+        .and_then(|segment| ApiVersion::from_prefix(segment));
+    if version.is_some() {
+        segments.remove(0);
+    }
+    (version, segments)
+}
+
+/// remove the version prefix and the empty segments of the request's path,
+/// returns the version
+///
+/// The empty segments are collapsed here, before any middleware looks at
+/// the path: the router ignores them, so `/v1//mainnet/txs/signed` would
+/// reach the handler of `/mainnet/txs/signed` while the middlewares would
+/// see a request about no network.
+fn strip_version(req: &mut Request) -> ApiVersion {
+    let (version, segments) = split_path(&req.url.path());
+    if version.is_some() || segments.len() != req.url.path().len() {
+        req.url.as_mut().set_path(&format!("/{}", segments.join("/")));
+    }
+    version.unwrap_or(ApiVersion::V1)
+}
+
+pub struct StripVersion;
+
+struct StripVersionHandler {
+    handler: Box<dyn Handler>,
+}
+
+impl Handler for StripVersionHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let version = strip_version(req);
+        req.extensions.insert::<ApiVersion>(version);
+        self.handler.handle(req)
+    }
+}
+
+impl AroundMiddleware for StripVersion {
+    fn around(self, handler: Box<dyn Handler>) -> Box<dyn Handler> {
+        Box::new(StripVersionHandler { handler: handler })
+    }
+}
+
+/// dispatch the requests to the router of their version
+pub struct VersionRouter {
+    v1: Router,
+    v2: Router,
+}
+
+impl VersionRouter {
+    pub fn new(v1: Router, v2: Router) -> Self {
+        VersionRouter { v1: v1, v2: v2 }
+    }
+}
+
+impl Handler for VersionRouter {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        match get_version(req) {
+            ApiVersion::V1 => self.v1.handle(req),
+            ApiVersion::V2 => self.v2.handle(req),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn split(path: &str) -> (Option<ApiVersion>, String) {
+        let segments = path[1..].split('/').collect::<Vec<_>>();
+        let (version, segments) = split_path(&segments);
+        (version, segments.join("/"))
+    }
+
+    #[test]
+    fn version_is_stripped() {
+        assert_eq!(split("/v2/mainnet/tip"), (Some(ApiVersion::V2), "mainnet/tip".to_string()));
+        assert_eq!(split("/mainnet/tip"), (None, "mainnet/tip".to_string()));
+        assert_eq!(split("/v1"), (Some(ApiVersion::V1), "".to_string()));
+    }
+
+    #[test]
+    fn empty_segments_are_collapsed() {
+        assert_eq!(
+            split("/v1//mainnet/txs/signed"),
+            (Some(ApiVersion::V1), "mainnet/txs/signed".to_string())
+        );
+        assert_eq!(split("//mainnet//tip/"), (None, "mainnet/tip".to_string()));
+        assert_eq!(split("//v2/mainnet/tip"), (Some(ApiVersion::V2), "mainnet/tip".to_string()));
+    }
+}
@@ -0,0 +1,589 @@
+//! notify external services of the activity of watched addresses
+//!
+//! Operators register webhooks in the bridge configuration: a URL, a secret
+//! and a list of addresses of a given network. Every time the refresher
+//! applies new blocks, a JSON notification is POSTed to the webhook for
+//! every transaction spending from or paying to one of its addresses.
+//!
+//! The body of the notification is signed with HMAC-SHA256 keyed with the
+//! webhook's secret; the signature is sent hex encoded in the
+//! `X-Cardano-Signature` header so receivers can authenticate the bridge.
+
+use cardano::address::ExtendedAddr;
+use cardano::block::{Block, HeaderHash, Utxos};
+use cardano::coin::Coin;
+use cardano::config::GenesisData;
+use cardano::tx::{Tx, TxId, TxoPointer};
+use cardano::util::hex;
+use cardano_storage::{chain_state, Storage};
+use cryptoxide::{hmac::Hmac, mac::Mac, sha2::Sha256};
+use hyper::{self, header::ContentType, header::Headers, net::HttpsConnector};
+use hyper_openssl::OpensslClient;
+use openssl::error::ErrorStack;
+use std::{
+    fmt,
+    io::Read,
+    str::FromStr,
+    sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+use super::chain::{self, BlockPoint, Fork};
+
+/// the name of the HTTP header holding the signature of the notification
+pub const SIGNATURE_HEADER: &'static str = "X-Cardano-Signature";
+
+/// number of times the delivery of a notification is attempted
+const MAX_DELIVERY_ATTEMPTS: u32 = 5;
+
+/// delay before the first retry, doubled after every failed attempt
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// number of notifications waiting for delivery to a webhook, the new ones
+/// are dropped when its queue is full
+const MAX_QUEUED_DELIVERIES: usize = 1000;
+
+/// time a receiver has to accept the notification and to reply
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidAddress(String),
+    TlsError(ErrorStack),
+    HttpError(hyper::Error),
+    UnexpectedStatus(hyper::status::StatusCode),
+}
+impl From<ErrorStack> for Error {
+    fn from(e: ErrorStack) -> Error {
+        Error::TlsError(e)
+    }
+}
+impl From<hyper::Error> for Error {
+    fn from(e: hyper::Error) -> Error {
+        Error::HttpError(e)
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidAddress(addr) => write!(f, "invalid address `{}'", addr),
+            Error::TlsError(err) => write!(f, "unable to set up TLS: {}", err),
+            Error::HttpError(err) => write!(f, "{}", err),
+            Error::UnexpectedStatus(status) => write!(f, "receiver replied {}", status),
+        }
+    }
+}
+
+/// a webhook as registered in the configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    /// the URL the notifications are POSTed to
+    pub url: String,
+    /// the key used to sign the notifications
+    pub secret: String,
+    /// the name of the network the addresses belong to
+    pub network: String,
+    /// the base58 encoded addresses to watch
+    pub addresses: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Output {
+    txid: TxId,
+    index: u32,
+    coin: Coin,
+}
+
+#[derive(Debug, Serialize)]
+struct Notification<'a> {
+    network: &'a str,
+    address: &'a ExtendedAddr,
+    txid: TxId,
+    block: &'a BlockPoint,
+    /// the outputs of the transaction paying to the address
+    received: Vec<Output>,
+    /// the outputs of the address the transaction spends
+    spent: Vec<Output>,
+}
+
+struct Watcher {
+    webhook: Webhook,
+    addresses: Vec<ExtendedAddr>,
+    /// the queue of the bodies to deliver to the webhook
+    sender: Mutex<SyncSender<String>>,
+}
+
+pub struct Notifier {
+    watchers: Vec<Watcher>,
+    /// the number of blocks walked back from the previous tip after which
+    /// the blocks of a refresh are not notified
+    max_fork_depth: usize,
+}
+
+impl Notifier {
+    /// create the notifier for the given webhooks and start the threads
+    /// delivering the notifications, one per webhook so a receiver which
+    /// does not answer does not delay the others
+    pub fn new(webhooks: Vec<Webhook>, max_fork_depth: usize) -> Result<Self, Error> {
+        let mut watchers = Vec::with_capacity(webhooks.len());
+        for webhook in webhooks {
+            let mut addresses = Vec::with_capacity(webhook.addresses.len());
+            for address in webhook.addresses.iter() {
+                match ExtendedAddr::from_str(address) {
+                    Err(_) => return Err(Error::InvalidAddress(address.clone())),
+                    Ok(addr) => addresses.push(addr),
+                }
+            }
+            let client = client()?;
+            let (sender, receiver) = sync_channel(MAX_QUEUED_DELIVERIES);
+            let (url, secret) = (webhook.url.clone(), webhook.secret.clone());
+            thread::spawn(move || deliver_all(&client, &url, &secret, receiver));
+            watchers.push(Watcher {
+                webhook: webhook,
+                addresses: addresses,
+                sender: Mutex::new(sender),
+            });
+        }
+
+        Ok(Notifier {
+            watchers: watchers,
+            max_fork_depth: max_fork_depth,
+        })
+    }
+
+    /// tell if any webhook watches addresses of the given network
+    pub fn watches(&self, network: &str) -> bool {
+        self.watchers.iter().any(|w| w.webhook.network == network)
+    }
+
+    /// send the notifications for the transactions of the blocks applied
+    /// between the tips `from` and `to`
+    ///
+    /// If `to` is on a different branch than `from`, only the blocks of the
+    /// new branch are considered.
+    pub fn notify_blocks(
+        &self,
+        network: &str,
+        storage: &Storage,
+        genesis_data: &GenesisData,
+        genesis: &HeaderHash,
+        from: &HeaderHash,
+        to: &HeaderHash,
+    ) -> chain::Result<()> {
+        let watchers: Vec<&Watcher> = self
+            .watchers
+            .iter()
+            .filter(|w| w.webhook.network == network)
+            .collect();
+        if watchers.is_empty() {
+            return Ok(());
+        }
+
+        let max_depth = Some(self.max_fork_depth);
+        let fork = match chain::find_fork(storage, genesis, from, to, max_depth) {
+            Err(chain::Error::ForkTooDeep(max)) => {
+                warn!(
+                    "Network {:?} moved more than {} blocks, skipping the webhooks notifications",
+                    network, max
+                );
+                return Ok(());
+            }
+            fork => fork?,
+        };
+
+        // the UTxOs are needed to know the addresses the inputs spend from
+        let utxos =
+            chain_state::restore_chain_state(storage, genesis_data, &fork.ancestor.hash)?.utxos;
+
+        notify_fork(
+            network,
+            &watchers,
+            utxos,
+            &fork,
+            |point| match chain::read_block(storage, &point.hash)?.decode()? {
+                Block::BoundaryBlock(_) => Ok(vec![]),
+                Block::MainBlock(mblk) => {
+                    Ok(mblk.body.tx.iter().map(|txaux| txaux.tx.clone()).collect())
+                }
+            },
+            |watcher, notification| watcher.send(notification),
+        )
+    }
+}
+
+/// call `notify` for the transactions of the blocks the fork applies (its
+/// `right` branch), `utxos` being the UTxOs at its ancestor
+///
+/// The blocks of the `left` branch are rolled back, their transactions
+/// are not notified again.
+fn notify_fork<R, N>(
+    network: &str,
+    watchers: &[&Watcher],
+    mut utxos: Utxos,
+    fork: &Fork,
+    mut read_txs: R,
+    mut notify: N,
+) -> chain::Result<()>
+where
+    R: FnMut(&BlockPoint) -> chain::Result<Vec<Tx>>,
+    N: FnMut(&Watcher, &Notification),
+{
+    for point in fork.right.iter().rev() {
+        for tx in read_txs(point)? {
+            let txid = tx.id();
+
+            for watcher in watchers.iter() {
+                for address in watcher.addresses.iter() {
+                    let received: Vec<Output> = tx
+                        .outputs
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, txout)| txout.address == *address)
+                        .map(|(index, txout)| Output {
+                            txid: txid,
+                            index: index as u32,
+                            coin: txout.value,
+                        })
+                        .collect();
+                    let spent: Vec<Output> = tx
+                        .inputs
+                        .iter()
+                        .filter_map(|input| match utxos.get(input) {
+                            Some(txout) if txout.address == *address => Some(Output {
+                                txid: input.id,
+                                index: input.index,
+                                coin: txout.value,
+                            }),
+                            _ => None,
+                        })
+                        .collect();
+                    if received.is_empty() && spent.is_empty() {
+                        continue;
+                    }
+
+                    let notification = Notification {
+                        network: network,
+                        address: address,
+                        txid: txid,
+                        block: point,
+                        received: received,
+                        spent: spent,
+                    };
+                    notify(watcher, &notification);
+                }
+            }
+
+            for input in tx.inputs.iter() {
+                utxos.remove(input);
+            }
+            for (index, txout) in tx.outputs.iter().enumerate() {
+                utxos.insert(
+                    TxoPointer {
+                        id: txid,
+                        index: index as u32,
+                    },
+                    txout.clone(),
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Watcher {
+    fn send(&self, notification: &Notification) {
+        let body = serde_json::to_string(notification).unwrap();
+        match self.sender.lock().unwrap().try_send(body) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => error!(
+                "too many notifications waiting for {}, dropping the notification of {}",
+                self.webhook.url, notification.txid
+            ),
+            Err(TrySendError::Disconnected(_)) => {
+                error!("webhook delivery thread of {} is gone", self.webhook.url)
+            }
+        }
+    }
+}
+
+/// compute the hex encoded HMAC-SHA256 of the body
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::new(Sha256::new(), secret.as_bytes());
+    mac.input(body.as_bytes());
+    hex::encode(mac.result().code())
+}
+
+/// the HTTP(S) client delivering the notifications of a webhook
+fn client() -> Result<hyper::Client, Error> {
+    let ssl = OpensslClient::new()?;
+    let mut client = hyper::Client::with_connector(HttpsConnector::new(ssl));
+    client.set_read_timeout(Some(DELIVERY_TIMEOUT));
+    client.set_write_timeout(Some(DELIVERY_TIMEOUT));
+    Ok(client)
+}
+
+/// POST the signed body to the given URL, once
+fn deliver(client: &hyper::Client, url: &str, secret: &str, body: &str) -> Result<(), Error> {
+    let mut headers = Headers::new();
+    headers.set(ContentType::json());
+    headers.set_raw(SIGNATURE_HEADER, vec![sign(secret, body).into_bytes()]);
+
+    let mut res = client.post(url).headers(headers).body(body).send()?;
+    // drain the response so the connection can be reused
+    let _ = res.read_to_end(&mut vec![]);
+    if res.status.is_success() {
+        Ok(())
+    } else {
+        Err(Error::UnexpectedStatus(res.status))
+    }
+}
+
+/// deliver the bodies received to the webhook, in order
+fn deliver_all(client: &hyper::Client, url: &str, secret: &str, receiver: Receiver<String>) {
+    for body in receiver {
+        let mut delay = FIRST_RETRY_DELAY;
+        for attempt in 1..=MAX_DELIVERY_ATTEMPTS {
+            match deliver(client, url, secret, &body) {
+                Ok(()) => break,
+                Err(err) if attempt < MAX_DELIVERY_ATTEMPTS => {
+                    warn!(
+                        "webhook delivery to {} failed (attempt {}): {}",
+                        url, attempt, err
+                    );
+                    thread::sleep(delay);
+                    delay *= 2;
+                }
+                Err(err) => error!(
+                    "webhook delivery to {} failed, giving up: {}",
+                    url, err
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use cardano::block::{BlockDate, EpochSlotId};
+    use cardano::tx::TxOut;
+    use std::collections::BTreeMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    static WATCHED: &str = "DdzFFzCqrhsjcfsReoiHddtt3ih6YusHbNXMTAjCvi5vakqk6sHkXDbMkaYgAbZyiy6hNK4761cF33AaCog93vbwgXGEXKgmA52dhrhJ";
+    static OTHER: &str = "DdzFFzCqrhtD4c7dNAyVG29R64GapneLWUbVTECYywUsc6baB7FatGkTGcLWNj3hZnhXJ1ZD43ZBooiUVnVEGQSmEjrxdAP7YUk8dQze";
+
+    fn address(address: &str) -> ExtendedAddr {
+        ExtendedAddr::from_str(address).unwrap()
+    }
+
+    fn watcher() -> Watcher {
+        Watcher {
+            webhook: Webhook {
+                url: "http://127.0.0.1/notify".to_string(),
+                secret: "secret".to_string(),
+                network: "mainnet".to_string(),
+                addresses: vec![WATCHED.to_string()],
+            },
+            addresses: vec![address(WATCHED)],
+            sender: Mutex::new(sync_channel(1).0),
+        }
+    }
+
+    fn point(n: u8, parent: u8) -> BlockPoint {
+        BlockPoint {
+            hash: HeaderHash::new(&[n; 32]),
+            parent: HeaderHash::new(&[parent; 32]),
+            date: BlockDate::Normal(EpochSlotId {
+                epoch: 0,
+                slotid: n as u16,
+            }),
+        }
+    }
+
+    fn output(addr: &str, coin: u64) -> TxOut {
+        TxOut {
+            address: address(addr),
+            value: Coin::new(coin).unwrap(),
+        }
+    }
+
+    fn input(tx: &Tx, index: u32) -> TxoPointer {
+        TxoPointer {
+            id: tx.id(),
+            index: index,
+        }
+    }
+
+    /// the notifications of the fork as `(address, txid, received, spent)`,
+    /// `blocks` giving the transactions of the blocks by hash
+    fn notifications(
+        utxos: Utxos,
+        fork: &Fork,
+        blocks: Vec<(BlockPoint, Vec<Tx>)>,
+    ) -> Vec<(ExtendedAddr, TxId, usize, usize)> {
+        let watcher = watcher();
+        let mut notified = vec![];
+        notify_fork(
+            "mainnet",
+            &[&watcher],
+            utxos,
+            fork,
+            |point| {
+                Ok(blocks
+                    .iter()
+                    .find(|(block, _)| block.hash == point.hash)
+                    .expect("unknown block")
+                    .1
+                    .clone())
+            },
+            |_, notification| {
+                notified.push((
+                    notification.address.clone(),
+                    notification.txid,
+                    notification.received.len(),
+                    notification.spent.len(),
+                ))
+            },
+        )
+        .unwrap();
+        notified
+    }
+
+    #[test]
+    fn notify_roll_forward() {
+        let genesis_tx = Tx::new_with(vec![], vec![output(WATCHED, 1000)]);
+        let mut utxos = BTreeMap::new();
+        utxos.insert(input(&genesis_tx, 0), output(WATCHED, 1000));
+
+        // pays 600 to someone else and the change back to the address
+        let pay = Tx::new_with(
+            vec![input(&genesis_tx, 0)],
+            vec![output(OTHER, 600), output(WATCHED, 400)],
+        );
+        // spends the change
+        let spend = Tx::new_with(vec![input(&pay, 1)], vec![output(OTHER, 400)]);
+        // does not involve the address
+        let unrelated = Tx::new_with(vec![input(&pay, 0)], vec![output(OTHER, 600)]);
+
+        let fork = Fork {
+            ancestor: point(1, 0),
+            left: vec![],
+            right: vec![point(3, 2), point(2, 1)],
+        };
+        let blocks = vec![
+            (point(2, 1), vec![pay.clone()]),
+            (point(3, 2), vec![unrelated.clone(), spend.clone()]),
+        ];
+
+        assert_eq!(
+            notifications(utxos, &fork, blocks),
+            vec![
+                (address(WATCHED), pay.id(), 1, 1),
+                (address(WATCHED), spend.id(), 0, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn notify_rollback() {
+        let genesis_tx = Tx::new_with(vec![], vec![output(OTHER, 1000)]);
+        let mut utxos = BTreeMap::new();
+        utxos.insert(input(&genesis_tx, 0), output(OTHER, 1000));
+
+        // the block rolled back paid everything to the address, the one
+        // replacing it only half of it
+        let rolled_back =
+            Tx::new_with(vec![input(&genesis_tx, 0)], vec![output(WATCHED, 1000)]);
+        let applied = Tx::new_with(
+            vec![input(&genesis_tx, 0)],
+            vec![output(OTHER, 500), output(WATCHED, 500)],
+        );
+
+        let fork = Fork {
+            ancestor: point(1, 0),
+            left: vec![point(2, 1)],
+            right: vec![point(3, 1)],
+        };
+        let blocks = vec![
+            (point(2, 1), vec![rolled_back]),
+            (point(3, 1), vec![applied.clone()]),
+        ];
+
+        assert_eq!(
+            notifications(utxos, &fork, blocks),
+            vec![(address(WATCHED), applied.id(), 1, 0)]
+        );
+    }
+
+    /// a minimal HTTP receiver accepting one request and replying with
+    /// the given status line; returns the URL to POST to and a handle
+    /// yielding the headers and body of the received request.
+    fn stand_in_receiver(
+        status_line: &'static str,
+    ) -> (String, thread::JoinHandle<(Vec<String>, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/notify", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut headers = vec![];
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_string();
+                if line.is_empty() {
+                    break;
+                }
+                headers.push(line);
+            }
+            let length = headers
+                .iter()
+                .find_map(|h| {
+                    let mut kv = h.splitn(2, ':');
+                    if kv.next()?.eq_ignore_ascii_case("content-length") {
+                        kv.next()?.trim().parse::<usize>().ok()
+                    } else {
+                        None
+                    }
+                })
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let mut stream = stream;
+            write!(stream, "{}\r\nContent-Length: 0\r\n\r\n", status_line).unwrap();
+            (headers, String::from_utf8(body).unwrap())
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn deliver_signed_notification() {
+        let (url, receiver) = stand_in_receiver("HTTP/1.1 200 OK");
+        let body = r#"{"network":"mainnet"}"#;
+
+        deliver(&client().unwrap(), &url, "secret", body).unwrap();
+
+        let (headers, received) = receiver.join().unwrap();
+        assert_eq!(received, body);
+        let signature = format!("{}: {}", SIGNATURE_HEADER, sign("secret", body));
+        assert!(headers.iter().any(|h| h.eq_ignore_ascii_case(&signature)));
+    }
+
+    #[test]
+    fn deliver_rejected_notification() {
+        let (url, receiver) = stand_in_receiver("HTTP/1.1 500 Internal Server Error");
+
+        match deliver(&client().unwrap(), &url, "secret", "{}") {
+            Err(Error::UnexpectedStatus(status)) => {
+                assert_eq!(status, hyper::status::StatusCode::InternalServerError)
+            }
+            res => panic!("unexpected delivery result: {:?}", res),
+        }
+        receiver.join().unwrap();
+    }
+}